@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(Parser)]
+#[command(name = "sysinfo_database", version, about = "Record and query system information in an sqlite database")]
+pub struct Cli {
+    /// Config file to read instead of the default location
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Path of the sqlite database
+    #[arg(long, global = true)]
+    pub database: Option<PathBuf>,
+    /// Comma separated list of collectors to record (ram, disk, component, cpu, load, network, process, lifecycle, pressure, cgroup, session)
+    #[arg(long, global = true)]
+    pub collectors: Option<String>,
+    /// Timezone to show and enter datetimes in: local, UTC, an offset like +02:00 or a name like Europe/Berlin
+    #[arg(long, global = true)]
+    pub timezone: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Record samples until interrupted (or until --count samples have been taken)
+    Record {
+        /// Seconds between samples
+        #[arg(long)]
+        interval: Option<u64>,
+        /// Stop after this many samples
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Print records of one kind, optionally restricted to a datetime range
+    Query {
+        kind: RecordKind,
+        /// Start of the range (YYYY-MM-DD HH:MM:SS in --timezone), inclusive
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+        /// End of the range (YYYY-MM-DD HH:MM:SS in --timezone), inclusive
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+        /// Only records of this host
+        #[arg(long)]
+        host: Option<String>,
+        /// Only processes with this name (process records only)
+        #[arg(long)]
+        name: Option<String>,
+        /// Only temperatures within the configured margin of critical (component records only)
+        #[arg(long)]
+        near_critical: bool,
+    },
+    /// Record samples and print them as they are taken
+    Live {
+        /// Seconds between samples
+        #[arg(long)]
+        interval: Option<u64>,
+    },
+    /// List the hosts that have been recorded, with their latest system data and sample time span
+    Hosts,
+    /// Show how the system configuration (OS, kernel, CPU) of each host changed over time
+    History {
+        /// Only the history of this host
+        #[arg(long)]
+        host: Option<String>,
+    },
+    /// Export records of one kind as CSV
+    Export {
+        kind: RecordKind,
+        /// Start of the range (YYYY-MM-DD HH:MM:SS in --timezone), inclusive
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+        /// End of the range (YYYY-MM-DD HH:MM:SS in --timezone), inclusive
+        #[arg(long, requires = "from")]
+        to: Option<String>,
+        /// Only records of this host
+        #[arg(long)]
+        host: Option<String>,
+        /// File to write to instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Record continuously without a terminal until SIGTERM or SIGINT, SIGHUP reloads the config
+    Daemon {
+        /// Seconds between samples
+        #[arg(long)]
+        interval: Option<u64>,
+        /// File to write the process id to while running
+        #[arg(long)]
+        pid_file: Option<PathBuf>,
+    },
+    /// Delete the samples the retention settings have expired, then exit
+    Prune,
+    /// Check that range queries search the indexes instead of reading whole tables
+    Check {
+        /// Print the plan of every query, not only of those that read a whole table
+        #[arg(long)]
+        plans: bool,
+    },
+    /// Start the interactive menu (default when no subcommand is given)
+    Interactive,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RecordKind {
+    Sys,
+    Component,
+    Ram,
+    Disk,
+    // reads and writes per block device
+    DiskIo,
+    Cpu,
+    Load,
+    // pressure stall information
+    Pressure,
+    // resource usage per cgroup (container, service)
+    Cgroup,
+    Network,
+    Process,
+    // processes starting and exiting
+    ProcessEvent,
+    // number of login sessions
+    Session,
+    // logins and logouts
+    SessionEvent,
+    // derived from the samples rather than collected, e.g. reboots
+    Event,
+    // ram, disk and component samples aggregated per minute, hour and day
+    Rollup,
+}
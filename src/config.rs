@@ -0,0 +1,606 @@
+use std::{collections::HashMap, env, fs, path::{Path, PathBuf}};
+use regex::Regex;
+use serde::Deserialize;
+use crate::timestamp::{Zone, SECOND};
+
+const APP_DIR: &str = "sysinfo_db";
+// location used before the config system existed, still picked up if a database is already there
+const LEGACY_DB_PATH: &str = "./data/sysinfo.db";
+
+// Settings after layering defaults, the config file, environment variables and command line flags (in that order)
+pub struct Config {
+    pub database: PathBuf,
+    pub interval: u64,
+    pub collectors: Collectors,
+    pub output: Output,
+    pub processes: Processes,
+    pub components: Components,
+    pub cgroups: Cgroups,
+    pub retention: Retention,
+    // where the daemon writes its pid, no pid file is written when unset
+    pub pid_file: Option<PathBuf>,
+}
+
+pub struct Collectors {
+    pub ram: bool,
+    pub disk: bool,
+    pub component: bool,
+    pub cpu: bool,
+    pub load: bool,
+    pub network: bool,
+    pub process: bool,
+    pub lifecycle: bool,
+    pub pressure: bool,
+    pub cgroup: bool,
+    pub session: bool,
+}
+
+// Which processes the process collector keeps each tick: the top processes by each ranking
+pub struct Processes {
+    pub top: usize,
+    pub rank_by: Vec<ProcessRank>,
+}
+
+pub struct Components {
+    // degrees below a sensor's critical temperature from which its samples are flagged as near critical
+    pub critical_margin: f32,
+}
+
+// Which cgroups the cgroup collector records, by path below the cgroup root (e.g. "system.slice/nginx.service").
+// A cgroup is recorded when it matches an include pattern and no exclude pattern.
+pub struct Cgroups {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+}
+
+// A path pattern: * matches within one path segment, ** across segments and ? a single character
+pub struct Glob {
+    regex: Regex,
+}
+
+// How long samples are kept, in seconds. A table's own setting wins over the default, None keeps rows
+// forever. Expired rows are deleted batch rows at a time every interval seconds.
+#[derive(Clone)]
+pub struct Retention {
+    pub default: Option<u64>,
+    pub tables: HashMap<String, Option<u64>>,
+    pub batch: usize,
+    pub interval: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProcessRank {
+    Cpu,
+    Memory,
+    VirtualMemory,
+}
+
+pub struct Output {
+    // print every sample while recording, as the live feed does
+    pub print_samples: bool,
+    // write the column names as the first line of csv exports
+    pub csv_header: bool,
+    // datetimes are shown and entered in this timezone, they are stored in UTC
+    pub timezone: Zone,
+}
+
+// Values that can be set from the command line, None means not given
+#[derive(Clone, Default)]
+pub struct Overrides {
+    pub config: Option<PathBuf>,
+    pub database: Option<PathBuf>,
+    pub interval: Option<u64>,
+    pub collectors: Option<String>,
+    pub pid_file: Option<PathBuf>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    database: Option<PathBuf>,
+    interval: Option<u64>,
+    collectors: Option<Vec<String>>,
+    pid_file: Option<PathBuf>,
+    output: FileOutput,
+    processes: FileProcesses,
+    components: FileComponents,
+    cgroups: FileCgroups,
+    retention: FileRetention,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileOutput {
+    print_samples: Option<bool>,
+    csv_header: Option<bool>,
+    timezone: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileProcesses {
+    top: Option<usize>,
+    rank_by: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileComponents {
+    critical_margin: Option<f32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileCgroups {
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileRetention {
+    default: Option<String>,
+    batch: Option<usize>,
+    interval: Option<String>,
+    tables: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database: default_db_path(),
+            interval: 10,
+            collectors: Collectors { ram: true, disk: true, component: true, cpu: true, load: true, network: true, process: true, lifecycle: true, pressure: true, cgroup: true, session: true },
+            output: Output { print_samples: false, csv_header: true, timezone: Zone::Local },
+            processes: Processes { top: 10, rank_by: vec![ProcessRank::Cpu, ProcessRank::Memory] },
+            components: Components { critical_margin: 5.0 },
+            cgroups: Cgroups { include: vec![Glob::parse("**").unwrap()], exclude: Vec::new() },
+            retention: Retention { default: None, tables: HashMap::new(), batch: 5000, interval: 3600 },
+            pid_file: None,
+        }
+    }
+}
+
+impl Config {
+
+    pub fn load(overrides: &Overrides) -> Result<Config, String> {
+        let env_var = |name: &str| env::var(name).ok();
+        let path = overrides.config.clone()
+            .or_else(|| env_var("SYSINFO_DB_CONFIG").map(PathBuf::from));
+
+        let contents = match &path {
+            Some(path) => Some(fs::read_to_string(path)
+                .map_err(|e| format!("Could not read config file '{}': {}", path.display(), e))?),
+            None => {
+                // the default location is optional, a missing file just means defaults
+                default_config_path().and_then(|path| fs::read_to_string(path).ok())
+            }
+        };
+
+        Config::from_sources(contents.as_deref(), env_var, overrides)
+    }
+
+    pub fn from_sources<F>(file: Option<&str>, env_var: F, overrides: &Overrides) -> Result<Config, String>
+    where
+        F: Fn(&str) -> Option<String> {
+        let mut config = Config::default();
+
+        if let Some(contents) = file {
+            let file: FileConfig = toml::from_str(contents).map_err(|e| format!("Invalid config file: {}", e))?;
+            if let Some(database) = file.database {
+                config.database = database;
+            }
+            if let Some(interval) = file.interval {
+                config.interval = interval;
+            }
+            if let Some(collectors) = file.collectors {
+                config.collectors = Collectors::parse(&collectors)?;
+            }
+            if let Some(pid_file) = file.pid_file {
+                config.pid_file = Some(pid_file);
+            }
+            if let Some(print_samples) = file.output.print_samples {
+                config.output.print_samples = print_samples;
+            }
+            if let Some(csv_header) = file.output.csv_header {
+                config.output.csv_header = csv_header;
+            }
+            if let Some(timezone) = file.output.timezone {
+                config.output.timezone = Zone::parse(&timezone)?;
+            }
+            if let Some(top) = file.processes.top {
+                config.processes.top = top;
+            }
+            if let Some(rank_by) = file.processes.rank_by {
+                config.processes.rank_by = rank_by.iter().map(|name| ProcessRank::parse(name)).collect::<Result<_, _>>()?;
+            }
+            if let Some(critical_margin) = file.components.critical_margin {
+                config.components.critical_margin = critical_margin;
+            }
+            if let Some(include) = file.cgroups.include {
+                config.cgroups.include = include.iter().map(|pattern| Glob::parse(pattern)).collect::<Result<_, _>>()?;
+            }
+            if let Some(exclude) = file.cgroups.exclude {
+                config.cgroups.exclude = exclude.iter().map(|pattern| Glob::parse(pattern)).collect::<Result<_, _>>()?;
+            }
+            if let Some(default) = file.retention.default {
+                config.retention.default = parse_keep(&default)?;
+            }
+            if let Some(batch) = file.retention.batch {
+                config.retention.batch = batch;
+            }
+            if let Some(interval) = file.retention.interval {
+                config.retention.interval = parse_duration(&interval)?;
+            }
+            for (table, keep) in &file.retention.tables {
+                if !crate::retention::tables().any(|known| known == table) {
+                    let known: Vec<&str> = crate::retention::tables().collect();
+                    return Err(format!("Unknown table '{}' in retention.tables, expected one of {}", table, known.join(", ")));
+                }
+                config.retention.tables.insert(table.clone(), parse_keep(keep)?);
+            }
+        }
+
+        if let Some(database) = env_var("SYSINFO_DB_DATABASE") {
+            config.database = PathBuf::from(database);
+        }
+        if let Some(interval) = env_var("SYSINFO_DB_INTERVAL") {
+            config.interval = interval.parse().map_err(|_| format!("Invalid SYSINFO_DB_INTERVAL '{}'", interval))?;
+        }
+        if let Some(collectors) = env_var("SYSINFO_DB_COLLECTORS") {
+            config.collectors = Collectors::parse_list(&collectors)?;
+        }
+        if let Some(pid_file) = env_var("SYSINFO_DB_PID_FILE") {
+            config.pid_file = Some(PathBuf::from(pid_file));
+        }
+        if let Some(print_samples) = env_var("SYSINFO_DB_PRINT_SAMPLES") {
+            config.output.print_samples = parse_bool(&print_samples)
+                .ok_or(format!("Invalid SYSINFO_DB_PRINT_SAMPLES '{}'", print_samples))?;
+        }
+        if let Some(csv_header) = env_var("SYSINFO_DB_CSV_HEADER") {
+            config.output.csv_header = parse_bool(&csv_header)
+                .ok_or(format!("Invalid SYSINFO_DB_CSV_HEADER '{}'", csv_header))?;
+        }
+        if let Some(timezone) = env_var("SYSINFO_DB_TIMEZONE") {
+            config.output.timezone = Zone::parse(&timezone)?;
+        }
+        if let Some(top) = env_var("SYSINFO_DB_PROCESS_TOP") {
+            config.processes.top = top.parse().map_err(|_| format!("Invalid SYSINFO_DB_PROCESS_TOP '{}'", top))?;
+        }
+        if let Some(keep) = env_var("SYSINFO_DB_RETENTION") {
+            config.retention.default = parse_keep(&keep).map_err(|e| format!("Invalid SYSINFO_DB_RETENTION: {}", e))?;
+        }
+        if let Some(margin) = env_var("SYSINFO_DB_CRITICAL_MARGIN") {
+            config.components.critical_margin = margin.parse().map_err(|_| format!("Invalid SYSINFO_DB_CRITICAL_MARGIN '{}'", margin))?;
+        }
+
+        if let Some(database) = &overrides.database {
+            config.database = database.clone();
+        }
+        if let Some(interval) = overrides.interval {
+            config.interval = interval;
+        }
+        if let Some(collectors) = &overrides.collectors {
+            config.collectors = Collectors::parse_list(collectors)?;
+        }
+        if let Some(pid_file) = &overrides.pid_file {
+            config.pid_file = Some(pid_file.clone());
+        }
+        if let Some(timezone) = &overrides.timezone {
+            config.output.timezone = Zone::parse(timezone)?;
+        }
+
+        if config.interval == 0 {
+            return Err("The sampling interval must be at least 1 second".to_string());
+        }
+        if config.processes.top == 0 || config.processes.rank_by.is_empty() {
+            return Err("processes.top must be at least 1 and processes.rank_by must not be empty, remove \"process\" from the collectors to stop recording processes".to_string());
+        }
+        if config.retention.batch == 0 || config.retention.interval == 0 {
+            return Err("retention.batch and retention.interval must be at least 1".to_string());
+        }
+        if !(config.components.critical_margin >= 0.0 && config.components.critical_margin.is_finite()) {
+            return Err("components.critical_margin must be a number of degrees, 0 or more".to_string());
+        }
+        Ok(config)
+    }
+
+    // creates the directories the database lives in so opening it does not fail on a fresh machine
+    pub fn create_db_dir(&self) -> Result<(), String> {
+        match self.database.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create '{}': {}", dir.display(), e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Collectors {
+
+    fn none() -> Self {
+        Collectors { ram: false, disk: false, component: false, cpu: false, load: false, network: false, process: false, lifecycle: false, pressure: false, cgroup: false, session: false }
+    }
+
+    fn parse(names: &[String]) -> Result<Self, String> {
+        let mut collectors = Collectors::none();
+        for name in names {
+            match name.trim() {
+                "ram" => collectors.ram = true,
+                "disk" => collectors.disk = true,
+                "component" => collectors.component = true,
+                "cpu" => collectors.cpu = true,
+                "load" => collectors.load = true,
+                "network" => collectors.network = true,
+                "process" => collectors.process = true,
+                "lifecycle" => collectors.lifecycle = true,
+                "pressure" => collectors.pressure = true,
+                "cgroup" => collectors.cgroup = true,
+                "session" => collectors.session = true,
+                other => return Err(format!("Unknown collector '{}'", other)),
+            }
+        }
+        Ok(collectors)
+    }
+
+    // comma separated form used by the environment and command line
+    fn parse_list(list: &str) -> Result<Self, String> {
+        let names: Vec<String> = list.split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| name.to_string())
+            .collect();
+        Collectors::parse(&names)
+    }
+}
+
+impl Retention {
+
+    pub fn keep(&self, table: &str) -> Option<u64> {
+        // rollups are there to outlive the samples they summarize, the default does not apply to them
+        if crate::rollup::TABLES.contains(&table) {
+            return self.tables.get(table).copied().flatten();
+        }
+        self.tables.get(table).copied().unwrap_or(self.default)
+    }
+
+    // whether anything expires at all
+    pub fn enabled(&self) -> bool {
+        self.default.is_some() || self.tables.values().any(Option::is_some)
+    }
+}
+
+impl Cgroups {
+
+    pub fn wanted(&self, path: &str) -> bool {
+        self.include.iter().any(|glob| glob.matches(path)) && !self.exclude.iter().any(|glob| glob.matches(path))
+    }
+}
+
+impl Glob {
+
+    fn parse(pattern: &str) -> Result<Self, String> {
+        if pattern.trim().is_empty() {
+            return Err("Empty cgroup pattern".to_string());
+        }
+        let mut regex = String::from("^");
+        let mut chars = pattern.trim().trim_matches('/').chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    regex.push_str(".*");
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        let regex = Regex::new(&regex).map_err(|e| format!("Invalid cgroup pattern '{}': {}", pattern, e))?;
+        Ok(Glob { regex })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.regex.is_match(path)
+    }
+}
+
+impl ProcessRank {
+
+    fn parse(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "cpu" => Ok(ProcessRank::Cpu),
+            "memory" => Ok(ProcessRank::Memory),
+            "virtual_memory" => Ok(ProcessRank::VirtualMemory),
+            other => Err(format!("Unknown process ranking '{}', expected cpu, memory or virtual_memory", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessRank::Cpu => "cpu",
+            ProcessRank::Memory => "memory",
+            ProcessRank::VirtualMemory => "virtual_memory",
+        }
+    }
+}
+
+// A number of seconds, minutes, hours, days or weeks: "90s", "15m", "12h", "7d", "2w"
+fn parse_duration(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let invalid = || format!("Invalid duration '{}', expected a number followed by s, m, h, d or w (e.g. \"7d\")", value);
+    let split = value.len().checked_sub(1).filter(|split| *split > 0 && value.is_char_boundary(*split)).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(invalid()),
+    };
+    // timestamps are i64 milliseconds, a longer duration can't be subtracted from one
+    let seconds = number.checked_mul(unit)
+        .filter(|seconds| i64::try_from(*seconds).ok().and_then(|seconds| seconds.checked_mul(SECOND)).is_some())
+        .ok_or_else(|| format!("Invalid duration '{}', too long", value))?;
+    match seconds {
+        0 => Err(invalid()),
+        seconds => Ok(seconds),
+    }
+}
+
+// a retention period, "forever" keeps the rows
+fn parse_keep(value: &str) -> Result<Option<u64>, String> {
+    match value.trim() {
+        "forever" => Ok(None),
+        value => parse_duration(value).map(Some),
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) if Path::new(&dir).is_absolute() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join(APP_DIR).join("config.toml"))
+}
+
+fn default_db_path() -> PathBuf {
+    if Path::new(LEGACY_DB_PATH).exists() {
+        return PathBuf::from(LEGACY_DB_PATH);
+    }
+    match xdg_dir("XDG_DATA_HOME", ".local/share") {
+        Some(dir) => dir.join(APP_DIR).join("sysinfo.db"),
+        None => PathBuf::from(LEGACY_DB_PATH),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn file_values_override_defaults() {
+        let file = r#"
+            database = "/tmp/metrics.db"
+            interval = 30
+            collectors = ["ram", "disk"]
+
+            [output]
+            print_samples = true
+        "#;
+        let config = Config::from_sources(Some(file), no_env, &Overrides::default()).unwrap();
+        assert_eq!(config.database, PathBuf::from("/tmp/metrics.db"));
+        assert_eq!(config.interval, 30);
+        assert!(config.collectors.ram && config.collectors.disk && !config.collectors.component);
+        assert!(config.output.print_samples);
+        assert!(config.output.csv_header);
+        assert_eq!(config.processes.top, 10);
+        let file = "[processes]\ntop = 3\nrank_by = [\"virtual_memory\"]";
+        let config = Config::from_sources(Some(file), no_env, &Overrides::default()).unwrap();
+        assert_eq!((config.processes.top, config.processes.rank_by), (3, vec![ProcessRank::VirtualMemory]));
+        assert_eq!(config.components.critical_margin, 5.0);
+        let file = "[components]\ncritical_margin = 2.5";
+        let config = Config::from_sources(Some(file), no_env, &Overrides::default()).unwrap();
+        assert_eq!(config.components.critical_margin, 2.5);
+    }
+
+    #[test]
+    fn retention_is_read_per_table() {
+        let config = Config::from_sources(None, no_env, &Overrides::default()).unwrap();
+        assert!(!config.retention.enabled());
+        let file = "[retention]\ndefault = \"7d\"\ninterval = \"15m\"\n[retention.tables]\nprocess = \"36h\"\nevent = \"forever\"\nrollup_1m = \"30d\"";
+        let config = Config::from_sources(Some(file), no_env, &Overrides::default()).unwrap();
+        assert!(config.retention.enabled());
+        assert_eq!((config.retention.keep("ram"), config.retention.keep("process"), config.retention.keep("event")),
+            (Some(7 * 86400), Some(36 * 3600), None));
+        assert_eq!((config.retention.keep("rollup_1m"), config.retention.keep("rollup_1h")), (Some(30 * 86400), None));
+        assert_eq!((config.retention.interval, config.retention.batch), (900, 5000));
+        let env_var = |name: &str| (name == "SYSINFO_DB_RETENTION").then(|| "2w".to_string());
+        let config = Config::from_sources(Some(file), env_var, &Overrides::default()).unwrap();
+        assert_eq!(config.retention.keep("ram"), Some(14 * 86400));
+    }
+
+    #[test]
+    fn cgroup_patterns_select_paths() {
+        let config = Config::from_sources(None, no_env, &Overrides::default()).unwrap();
+        assert!(config.cgroups.wanted("system.slice/nginx.service"));
+        let file = "[cgroups]\ninclude = [\"system.slice/docker-*.scope\", \"/kubepods/**\"]\nexclude = [\"**/*-init.scope\"]";
+        let config = Config::from_sources(Some(file), no_env, &Overrides::default()).unwrap();
+        assert!(config.cgroups.wanted("system.slice/docker-4f2a.scope"));
+        assert!(config.cgroups.wanted("kubepods/burstable/pod1/abc"));
+        // * stays within one path segment
+        assert!(!config.cgroups.wanted("system.slice/docker-4f2a.scope/child"));
+        assert!(!config.cgroups.wanted("system.slice/nginx.service"));
+        assert!(!config.cgroups.wanted("system.slice/docker-4f2a-init.scope"));
+        // regex characters in patterns are literal
+        assert!(Glob::parse("user.slice").unwrap().matches("user.slice"));
+        assert!(!Glob::parse("user.slice").unwrap().matches("userXslice"));
+    }
+
+    #[test]
+    fn env_overrides_file_and_flags_override_env() {
+        let file = "interval = 30\ndatabase = \"/tmp/file.db\"";
+        let env_var = |name: &str| match name {
+            "SYSINFO_DB_INTERVAL" => Some("20".to_string()),
+            "SYSINFO_DB_DATABASE" => Some("/tmp/env.db".to_string()),
+            "SYSINFO_DB_COLLECTORS" => Some("component".to_string()),
+            "SYSINFO_DB_CSV_HEADER" => Some("false".to_string()),
+            _ => None,
+        };
+        let overrides = Overrides { interval: Some(5), ..Default::default() };
+        let config = Config::from_sources(Some(file), env_var, &overrides).unwrap();
+        assert!(!config.output.csv_header);
+        assert_eq!(config.interval, 5);
+        assert_eq!(config.database, PathBuf::from("/tmp/env.db"));
+        assert!(config.collectors.component && !config.collectors.ram);
+    }
+
+    #[test]
+    fn timezone_is_layered_like_the_rest() {
+        let config = Config::from_sources(None, no_env, &Overrides::default()).unwrap();
+        assert_eq!(config.output.timezone, Zone::Local);
+        let file = "[output]\ntimezone = \"Europe/Berlin\"";
+        let config = Config::from_sources(Some(file), no_env, &Overrides::default()).unwrap();
+        assert_eq!(config.output.timezone, Zone::parse("Europe/Berlin").unwrap());
+        let env_var = |name: &str| (name == "SYSINFO_DB_TIMEZONE").then(|| "UTC".to_string());
+        let config = Config::from_sources(Some(file), env_var, &Overrides::default()).unwrap();
+        assert_eq!(config.output.timezone, Zone::Utc);
+        let overrides = Overrides { timezone: Some("+09:00".to_string()), ..Default::default() };
+        let config = Config::from_sources(Some(file), env_var, &overrides).unwrap();
+        assert_eq!(config.output.timezone, Zone::parse("+09:00").unwrap());
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(Config::from_sources(Some("colour = \"red\""), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("collectors = [\"gpu\"]"), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("interval = 0"), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("[processes]\nrank_by = [\"io\"]"), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("[processes]\ntop = 0"), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("[components]\ncritical_margin = -1.0"), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("[cgroups]\ninclude = [\"\"]"), no_env, &Overrides::default()).is_err());
+        assert!(Config::from_sources(Some("[output]\ntimezone = \"Berlin\""), no_env, &Overrides::default()).is_err());
+        for retention in ["default = \"7\"", "default = \"0d\"", "default = \"d\"", "default = \"7y\"", "default = \"300000000000000d\"", "batch = 0", "[retention.tables]\nsys = \"1d\""] {
+            assert!(Config::from_sources(Some(&format!("[retention]\n{}", retention)), no_env, &Overrides::default()).is_err(), "{}", retention);
+        }
+        let env_var = |_: &str| Some("often".to_string());
+        assert!(Config::from_sources(None, env_var, &Overrides::default()).is_err());
+    }
+}
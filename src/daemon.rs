@@ -0,0 +1,181 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, ErrorKind, Read, Write}, os::fd::AsRawFd, path::{Path, PathBuf}, process, sync::{mpsc, Arc}, thread, time::Duration};
+use signal_hook::{consts::{SIGHUP, SIGINT, SIGTERM}, iterator::Signals};
+use crate::config::{Config, Overrides};
+use crate::db::Database;
+use crate::retention::Maintenance;
+use crate::scheduler::{Control, Scheduler, Wake};
+use crate::timestamp::Timestamp;
+use crate::{write_all_records, write_sysdata, Collector};
+
+// Removes the pid file when the daemon exits, however it exits. The file is locked for as long as the
+// daemon runs: two daemons started at once can't both get the lock, and the kernel releases the lock of
+// a daemon that crashed, so a file nobody holds is left over and can be replaced.
+struct PidFile {
+    path: PathBuf,
+    // holds the lock, closed after the file is removed
+    _file: File,
+}
+
+impl PidFile {
+
+    fn create(path: &Path) -> Result<PidFile, String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create '{}': {}", dir.display(), e))?;
+        }
+        // not truncated before the lock is held, it may be the pid of the running daemon
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+            .map_err(|e| format!("Could not open pid file '{}': {}", path.display(), e))?;
+        // SAFETY: the descriptor belongs to file, which is open for the whole call
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() != ErrorKind::WouldBlock {
+                return Err(format!("Could not lock pid file '{}': {}", path.display(), e));
+            }
+            let mut pid = String::new();
+            let _ = file.read_to_string(&mut pid);
+            return match pid.trim() {
+                // locked but not written yet, the other daemon is starting
+                "" => Err(format!("Daemon already running (pid file '{}')", path.display())),
+                pid => Err(format!("Daemon already running with pid {} (pid file '{}')", pid, path.display())),
+            };
+        }
+
+        let written = file.set_len(0).and_then(|_| file.write_all(format!("{}\n", process::id()).as_bytes()));
+        written.map_err(|e| format!("Could not write pid file '{}': {}", path.display(), e))?;
+        Ok(PidFile { path: path.to_path_buf(), _file: file })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn log(message: &str) {
+    println!("[{}] {}", Timestamp::now(), message);
+}
+
+// Forwards SIGTERM/SIGINT/SIGHUP to a channel so they are only acted on between samples
+pub fn listen_for_signals() -> Result<mpsc::Receiver<Control>, String> {
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP])
+        .map_err(|e| format!("Could not install signal handlers: {}", e))?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            let signal = match signal {
+                SIGHUP => Control::Reload,
+                _ => Control::Shutdown,
+            };
+            if tx.send(signal).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+pub fn run(conn: Arc<Database>, config: Arc<Config>, overrides: Overrides) -> Result<(), String> {
+    let signals = listen_for_signals()?;
+    let _pid_file = match &config.pid_file {
+        Some(path) => Some(PidFile::create(path)?),
+        None => None,
+    };
+    let mut config = config;
+
+    log(&format!("Recording to '{}' every {}s", config.database.display(), config.interval));
+    let mut collector = Collector::new();
+    let host_id = write_sysdata(&mut collector.sys, conn.clone()).map_err(|e| format!("Could not register this host: {}", e))?;
+    let mut maintenance = Maintenance::start(conn.clone(), config.retention.clone(), log);
+
+    let mut scheduler = Scheduler::new(Duration::from_secs(config.interval));
+    let mut written = 0;
+    let mut failed = 0;
+    loop {
+        match scheduler.wait(&signals) {
+            Wake::Tick { missed } => {
+                if missed > 0 {
+                    log(&format!("Missed {} sample(s), the previous sample took longer than {}s", missed, config.interval));
+                }
+                let stats = write_all_records(&mut collector, conn.clone(), host_id, &config, config.output.print_samples);
+                stats.report(log);
+                written += stats.written;
+                failed += stats.failed;
+            }
+            Wake::Message(Control::Reload) => match Config::load(&overrides) {
+                Ok(reloaded) => {
+                    if reloaded.database != config.database {
+                        log("The database path cannot change while running, restart the daemon to use the new path");
+                    }
+                    log(&format!("Reloaded config, recording every {}s", reloaded.interval));
+                    scheduler.set_interval(Duration::from_secs(reloaded.interval));
+                    // restarted so the new retention applies right away
+                    if let Some(maintenance) = maintenance.take() {
+                        maintenance.stop();
+                    }
+                    maintenance = Maintenance::start(conn.clone(), reloaded.retention.clone(), log);
+                    config = Arc::new(reloaded);
+                }
+                Err(e) => log(&format!("Config reload failed, keeping the current config: {}", e)),
+            },
+            Wake::Message(Control::Shutdown) | Wake::Disconnected => break,
+            // not sent by the signal handler
+            Wake::Message(Control::Pause | Control::Record | Control::Live) => {}
+        }
+    }
+
+    if let Some(maintenance) = maintenance {
+        maintenance.stop();
+    }
+    log(&format!("Shutting down, wrote {} records ({} failed)", written, failed));
+    // every write is finished at this point, closing the connections reports anything sqlite could not flush
+    Arc::try_unwrap(conn)
+        .map_err(|_| "Database connection still in use at shutdown".to_string())?
+        .close()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid_file_is_written_and_removed() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_test_{}.pid", process::id()));
+        {
+            let _pid_file = PidFile::create(&path).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap().trim(), process::id().to_string());
+            // the pid belongs to a live process so a second daemon is refused
+            assert!(PidFile::create(&path).is_err());
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn only_one_of_two_daemons_starting_at_once_gets_the_pid_file() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_test_{}_race.pid", process::id()));
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+        let starts: Vec<_> = (0..2).map(|_| {
+            let (path, barrier) = (path.clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                PidFile::create(&path)
+            })
+        }).collect();
+        let results: Vec<_> = starts.into_iter().map(|start| start.join().unwrap()).collect();
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        drop(results);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_pid_file_left_by_a_crashed_daemon_is_replaced() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_test_{}_stale.pid", process::id()));
+        // nobody holds the lock on it, whatever pid it names
+        fs::write(&path, "1234567890\n").unwrap();
+        {
+            let _pid_file = PidFile::create(&path).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap().trim(), process::id().to_string());
+        }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{ops::Deref, path::Path, sync::Mutex, time::Duration};
+use rusqlite::{Connection, OpenFlags, Result};
+use crate::migrations;
+
+// The database of one process. Everything that writes shares the writer connection, queries take a
+// read-only connection from a pool. In WAL mode readers see the last committed state and neither block
+// the writer nor wait for it, so a long query in the menu can't make the recorder miss samples.
+
+// How long a statement waits for a lock held by another process (another recorder, a checkpoint, a
+// VACUUM) before failing with "database is locked"
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// idle read-only connections kept open, more are opened while that many queries run at once
+const IDLE_READERS: usize = 4;
+
+pub struct Database {
+    // the path or URI every connection opens
+    uri: String,
+    writer: Mutex<Connection>,
+    readers: Mutex<Vec<Connection>>,
+}
+
+impl Database {
+
+    // Opens the database at path, switches it to WAL journaling and migrates it to the latest schema
+    pub fn open(path: &Path) -> std::result::Result<Database, String> {
+        let uri = path.to_string_lossy().into_owned();
+        let failed = |e: rusqlite::Error| format!("Connection to '{}' failed. Make sure the path is correct and writable\n{}", path.display(), e);
+        let writer = Connection::open(path).map_err(failed)?;
+        Database::with_writer(uri, writer)
+    }
+
+    // A private in-memory database, shared by the connections of the pool through sqlite's shared cache.
+    // It has no WAL, so readers wait for the writer's transaction instead of seeing the state before it.
+    #[cfg(test)]
+    pub fn open_in_memory() -> Database {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static DATABASES: AtomicUsize = AtomicUsize::new(0);
+        let uri = format!("file:sysinfo_db_{}_{}?mode=memory&cache=shared", std::process::id(), DATABASES.fetch_add(1, Ordering::Relaxed));
+        let writer = Connection::open_with_flags(&uri, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_URI).unwrap();
+        Database::with_writer(uri, writer).unwrap()
+    }
+
+    fn with_writer(uri: String, mut writer: Connection) -> std::result::Result<Database, String> {
+        configure(&writer).map_err(|e| format!("Could not configure the database connection: {}", e))?;
+        // auto_vacuum can only be switched on for free before the first table is created, an existing
+        // database takes a full VACUUM which is left to the prune command
+        let tables: i64 = writer.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get(0))
+            .map_err(|e| format!("Could not read the database schema: {}", e))?;
+        if tables == 0 {
+            writer.pragma_update(None, "auto_vacuum", "INCREMENTAL")
+                .map_err(|e| format!("Could not enable incremental vacuum: {}", e))?;
+        }
+        // persistent, the database stays in WAL mode for every process that opens it later
+        let mode: String = writer.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))
+            .map_err(|e| format!("Could not switch the database to WAL journaling: {}", e))?;
+        if mode != "wal" && mode != "memory" {
+            return Err(format!("Could not switch the database to WAL journaling, it is in {} mode", mode));
+        }
+        migrations::migrate(&mut writer)?;
+        Ok(Database { uri, writer: Mutex::new(writer), readers: Mutex::new(Vec::new()) })
+    }
+
+    // The connection all writes go through. Hold it only for the write itself.
+    pub fn writer(&self) -> &Mutex<Connection> {
+        &self.writer
+    }
+
+    // A read-only connection for one query, back to the pool when dropped
+    pub fn reader(&self) -> Result<Reader<'_>> {
+        let idle = self.readers.lock().unwrap().pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = Connection::open_with_flags(&self.uri, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+                configure(&conn)?;
+                conn
+            }
+        };
+        Ok(Reader { conn: Some(conn), database: self })
+    }
+
+    // Closes every connection, reporting anything sqlite could not flush. The writer closes last so the
+    // WAL is checkpointed into the database file.
+    pub fn close(self) -> std::result::Result<(), String> {
+        for reader in self.readers.into_inner().unwrap() {
+            reader.close().map_err(|(_, e)| format!("Closing the database failed: {}", e))?;
+        }
+        self.writer.into_inner().unwrap().close().map_err(|(_, e)| format!("Closing the database failed: {}", e))
+    }
+}
+
+fn configure(conn: &Connection) -> Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)
+}
+
+pub struct Reader<'a> {
+    conn: Option<Connection>,
+    database: &'a Database,
+}
+
+impl Deref for Reader<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl Drop for Reader<'_> {
+    fn drop(&mut self) {
+        let mut idle = self.database.readers.lock().unwrap();
+        if idle.len() < IDLE_READERS {
+            idle.extend(self.conn.take());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_database(name: &str) -> (Database, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_{}_{}.db", name, std::process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        (Database::open(&path).unwrap(), path)
+    }
+
+    fn count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM host", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn readers_see_committed_writes_without_blocking_them() {
+        let (db, path) = temp_database("wal");
+        let mode: String = db.reader().unwrap().query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        // a query in progress keeps reading the state it started from while a write commits
+        let reader = db.reader().unwrap();
+        reader.execute_batch("BEGIN").unwrap();
+        assert_eq!(count(&reader), 0);
+        db.writer().lock().unwrap().execute("INSERT INTO host (hostname) VALUES ('alpha')", []).unwrap();
+        assert_eq!(count(&reader), 0);
+        assert_eq!(count(&db.reader().unwrap()), 1);
+        reader.execute_batch("COMMIT").unwrap();
+        assert_eq!(count(&reader), 1);
+
+        assert!(reader.execute("INSERT INTO host (hostname) VALUES ('beta')", []).is_err());
+        drop(reader);
+        db.close().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn new_databases_use_incremental_vacuum() {
+        let (db, path) = temp_database("auto_vacuum");
+        let mode: i64 = db.reader().unwrap().pragma_query_value(None, "auto_vacuum", |row| row.get(0)).unwrap();
+        assert_eq!(mode, 2);
+        db.close().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn readers_are_reused() {
+        let db = Database::open_in_memory();
+        db.writer().lock().unwrap().execute("INSERT INTO host (hostname) VALUES ('alpha')", []).unwrap();
+        let readers: Vec<Reader> = (0..IDLE_READERS + 2).map(|_| db.reader().unwrap()).collect();
+        assert!(readers.iter().all(|reader| count(reader) == 1));
+        drop(readers);
+        assert_eq!(db.readers.lock().unwrap().len(), IDLE_READERS);
+        let _reader = db.reader().unwrap();
+        assert_eq!(db.readers.lock().unwrap().len(), IDLE_READERS - 1);
+    }
+}
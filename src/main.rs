@@ -1,49 +1,227 @@
-use std::{fmt, sync::{mpsc::Sender, Arc, Mutex}, thread, time::Duration};
-use chrono::prelude::*;
-use rusqlite::{Connection, Result, Row};
-use sysinfo::{Components, Disks, System as SystemData};
+mod cli;
+mod config;
+mod daemon;
+mod db;
+mod migrations;
+mod plan;
+mod retention;
+mod rollup;
+mod scheduler;
+mod timestamp;
+
+use std::{collections::HashMap, ffi::CString, fmt, fs::{self, File}, mem, os::unix::ffi::OsStrExt, io::{self, Write}, path::{Path, PathBuf}, process, ptr, sync::{mpsc::Sender, Arc}, thread, time::{Duration, Instant}};
+use clap::Parser;
+use rusqlite::{params_from_iter, types::Value, Connection, Error, OptionalExtension, Result, Row, TransactionBehavior};
+use sysinfo::{Components, Disks, Networks, System as SystemData, Users};
 use regex::Regex;
 use std::sync::mpsc;
+use cli::{Cli, Command, RecordKind};
+use config::{Cgroups, Config, Overrides, ProcessRank, Processes};
+use db::Database;
+use retention::Maintenance;
+use scheduler::{Control, Scheduler, Wake};
+use timestamp::{Timestamp, Zone};
+
+// What a command returns: SQL, I/O and usage errors are all printed the same way
+type CommandResult = std::result::Result<(), Box<dyn std::error::Error>>;
 
 trait Record: Sized + fmt::Display {
-    fn write_to_db(&self, conn: Arc<Mutex<Connection>>) -> Result<()>;
+    // inserts using a cached statement, so it is cheap to call for every record of a tick
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()>;
+    // all records, ?1 is the hostname to filter by or NULL for every host
     fn query() -> &'static str;
-    fn query_by_dt(start_dt: String, end_dt: String) -> String;
+    // returns a static SQL string and the parameters to bind to it, never interpolate values into the SQL
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>);
+    // the samples themselves over a range, for exports. Only kinds that read long ranges from the
+    // rollups in query_by_dt differ.
+    fn query_raw_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        Self::query_by_dt(start, end, host)
+    }
     fn from_row(row: &Row) -> Result<Self>;
-    
+
+    // a single autocommitted insert, only used by tests now that samples go through write_sample
+    #[cfg(test)]
+    fn write_to_db(&self, conn: Arc<Database>, host_id: i64) -> Result<()> {
+        let conn = conn.writer().lock().unwrap();
+        self.insert(&conn, host_id)
+    }
+}
+
+// The rollup tables read back as the rows of a raw query, one row per bucket and series. The average
+// of a metric stands in for its value, columns the rollups don't keep are NULL. A macro so that each
+// resolution has its own static SQL string, in the order of rollup::TABLES.
+macro_rules! rollup_queries {
+    ($source:literal, $columns:literal) => {
+        [rollup_queries!("rollup_1m", $source, $columns), rollup_queries!("rollup_1h", $source, $columns),
+            rollup_queries!("rollup_1d", $source, $columns)]
+    };
+    ($table:literal, $source:literal, $columns:literal) => {
+        concat!("SELECT r.datetime, ", $columns, ", h.hostname FROM ", $table, " r LEFT JOIN host h ON h.id = r.host_id
+        WHERE r.source = '", $source, "' AND r.datetime BETWEEN ?1 AND ?2 AND (?3 IS NULL OR h.hostname = ?3)
+        GROUP BY r.host_id, r.series, r.datetime
+        ORDER BY r.datetime, r.series")
+    };
+}
+
+// The WHERE clause of the raw range queries, for the table aliased $t. Spelled out per host so sqlite
+// searches the (host_id, datetime) index of each matching host instead of scanning the table, samples
+// that could not be attributed to a host only match when no hostname is given.
+macro_rules! in_range {
+    ($t:literal) => {
+        concat!("(", $t, ".host_id IN (SELECT id FROM host WHERE ?3 IS NULL OR hostname = ?3) AND ", $t, ".datetime BETWEEN ?1 AND ?2
+                OR ?3 IS NULL AND ", $t, ".host_id IS NULL AND ", $t, ".datetime BETWEEN ?1 AND ?2)")
+    };
+}
+
+// ?1 start, ?2 end and ?3 host, the parameters of every range query
+fn range_params(start: Timestamp, end: Timestamp, host: Option<&str>) -> Vec<Value> {
+    vec![Value::Integer(start.millis()), Value::Integer(end.millis()), host_param(host)]
+}
+
+// the hostname to filter by, NULL for every host
+fn host_param(host: Option<&str>) -> Value {
+    host.map_or(Value::Null, |host| Value::Text(host.to_string()))
+}
+
+// "Host: name , " for records read back from the database, nothing for freshly collected ones
+fn host_label(host: &Option<String>) -> String {
+    match host {
+        Some(host) => format!("Host: {} , ", host),
+        None => String::new(),
+    }
+}
+
+// Returns the id of the host row for hostname, adding it if this is the first time it is seen
+fn host_id(conn: &Connection, hostname: &str) -> Result<i64> {
+    conn.execute("INSERT OR IGNORE INTO host (hostname) VALUES (?1)", [hostname])?;
+    conn.query_row("SELECT id FROM host WHERE hostname = ?1", [hostname], |row| row.get(0))
+}
+
+struct HostRecord {
+    hostname: String,
+    os: Option<String>,
+    osversion: Option<String>,
+    first_sample: Option<Timestamp>,
+    last_sample: Option<Timestamp>
+}
+
+impl fmt::Display for HostRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unknown = "unknown".to_string();
+        write!(f, "Hostname: {} , OS: {} , Version: {} , First Sample: {} , Last Sample: {}", self.hostname,
+                self.os.as_ref().unwrap_or(&unknown), self.osversion.as_ref().unwrap_or(&unknown),
+                self.first_sample.map_or(unknown.clone(), |dt| dt.to_string()), self.last_sample.map_or(unknown.clone(), |dt| dt.to_string()))
+    }
+}
+
+// Every host with its latest configuration and the span of its samples, optionally only the one called
+// host. Hosts are added by host_id and not time based, so they are not a Record.
+fn query_hosts(conn: Arc<Database>, host: Option<&str>) -> Result<Vec<HostRecord>> {
+    let conn = conn.reader()?;
+    // every table a host records to, so a host with only some collectors enabled has a span too
+    let span = |aggregate: &str| retention::SAMPLE_TABLES.iter()
+        .map(|table| format!("SELECT {aggregate}(datetime) AS datetime FROM {table} WHERE host_id = h.id"))
+        .collect::<Vec<_>>()
+        .join("\n                UNION ALL ");
+    let mut stmt = conn.prepare(&format!("SELECT h.hostname, s.os, s.osversion,
+            (SELECT MIN(datetime) FROM ({})),
+            (SELECT MAX(datetime) FROM ({}))
+        FROM host h LEFT JOIN sys s ON s.id = (SELECT MAX(id) FROM sys WHERE host_id = h.id)
+        WHERE (?1 IS NULL OR h.hostname = ?1)
+        ORDER BY h.hostname", span("MIN"), span("MAX")))?;
+    let record_iter = stmt.query_map([host], HostRecord::from_row)?;
+
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+impl HostRecord {
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(HostRecord {
+            hostname: row.get(0)?,
+            os: row.get(1)?,
+            osversion: row.get(2)?,
+            first_sample: row.get(3)?,
+            last_sample: row.get(4)?,
+        })
+    }
 }
 
+// One configuration of a host, a new one is recorded whenever anything but the boot time changes.
+// The details are None for configurations recorded before they were tracked.
 struct SysRecord {
     os: String,
     osversion: String,
-    hostname: String
+    hostname: String,
+    kernel_version: Option<String>,
+    arch: Option<String>,
+    cpu_brand: Option<String>,
+    cpu_cores: Option<u32>,
+    boot_time: Option<Timestamp>,
+    first_seen: Option<Timestamp>,
+    last_seen: Option<Timestamp>
+}
+
+impl SysRecord {
+
+    // "what old -> new" for every detail that differs from previous. The boot time is left out, reboots
+    // are events of the load collector rather than new configurations
+    fn changes_from(&self, previous: &SysRecord) -> Vec<String> {
+        fn show(value: &Option<String>) -> &str {
+            value.as_deref().unwrap_or("unknown")
+        }
+        let details = [
+            ("OS", Some(previous.os.clone()), Some(self.os.clone())),
+            ("Version", Some(previous.osversion.clone()), Some(self.osversion.clone())),
+            ("Kernel", previous.kernel_version.clone(), self.kernel_version.clone()),
+            ("Arch", previous.arch.clone(), self.arch.clone()),
+            ("CPU", previous.cpu_brand.clone(), self.cpu_brand.clone()),
+            ("Cores", previous.cpu_cores.map(|n| n.to_string()), self.cpu_cores.map(|n| n.to_string())),
+        ];
+        details.into_iter()
+            .filter(|(_, old, new)| old != new)
+            .map(|(what, old, new)| format!("{} {} -> {}", what, show(&old), show(&new)))
+            .collect()
+    }
 }
 
 impl fmt::Display for SysRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "OS: {} , Version: {} , Hostname: {}", self.os, self.osversion, self.hostname)
+        let unknown = "unknown".to_string();
+        write!(f, "OS: {} , Version: {} , Hostname: {} , Kernel: {} , Arch: {} , CPU: {} , Cores: {} , Boot Time: {} , First Seen: {} , Last Seen: {}",
+            self.os, self.osversion, self.hostname,
+            self.kernel_version.as_ref().unwrap_or(&unknown), self.arch.as_ref().unwrap_or(&unknown),
+            self.cpu_brand.as_ref().unwrap_or(&unknown), self.cpu_cores.map_or(unknown.clone(), |n| n.to_string()),
+            self.boot_time.map_or(unknown.clone(), |dt| dt.to_string()), self.first_seen.map_or(unknown.clone(), |dt| dt.to_string()),
+            self.last_seen.map_or(unknown.clone(), |dt| dt.to_string()))
     }
 }
 
 impl Record for SysRecord {
 
-    fn write_to_db(&self, conn: Arc<Mutex<Connection>>) -> Result<()> {
-        let conn = conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO sys (os, osversion, hostname) VALUES (?1, ?2, ?3)", 
-        (&self.os, &self.osversion, &self.hostname))?;
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO sys (os, osversion, hostname, kernel_version, arch, cpu_brand, cpu_cores, boot_time, first_seen, last_seen, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)")?;
+        stmt.execute((&self.os, &self.osversion, &self.hostname, &self.kernel_version, &self.arch, &self.cpu_brand,
+            &self.cpu_cores, &self.boot_time, &self.first_seen, &self.last_seen, host_id))?;
         Ok(())
     }
 
     fn query() -> &'static str {
-        "SELECT os, osversion, hostname FROM sys"
+        // oldest configuration first, so the rows of a host read as its history
+        "SELECT os, osversion, hostname, kernel_version, arch, cpu_brand, cpu_cores, boot_time, first_seen, last_seen FROM sys
+        WHERE (?1 IS NULL OR hostname = ?1)
+        ORDER BY hostname, id"
     }
 
-    fn query_by_dt(start_dt: String, end_dt: String) -> String {
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
         // no functionality currently needed for querying system records by datetime
-        let _ = start_dt;
-        let _ = end_dt;
-        "SELECT os, osversion, hostname FROM sys".to_string()
+        let _ = (start, end);
+        (SysRecord::query(), vec![host_param(host)])
     }
 
     fn from_row(row: &Row) -> Result<Self> {
@@ -51,41 +229,73 @@ impl Record for SysRecord {
             os: row.get(0)?,
             osversion: row.get(1)?,
             hostname: row.get(2)?,
+            kernel_version: row.get(3)?,
+            arch: row.get(4)?,
+            cpu_brand: row.get(5)?,
+            cpu_cores: row.get(6)?,
+            boot_time: row.get(7)?,
+            first_seen: row.get(8)?,
+            last_seen: row.get(9)?,
         })
     }
 }
 
 
+// A temperature sensor. max and critical are None when the sensor does not report them and for
+// samples recorded before they were tracked.
 struct ComponentRecord {
-    datetime: String,
+    datetime: Timestamp,
+    host: Option<String>,
     label: String,
-    temp: f32
+    temp: f32,
+    max: Option<f32>,
+    critical: Option<f32>,
+    // within the configured margin of critical when the sample was taken
+    near_critical: bool
 }
 
 impl fmt::Display for ComponentRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Time: {} , Label: {} , Temperature: {}", self.datetime, self.label, self.temp)
+        let degrees = |temp: Option<f32>| temp.map_or("unknown".to_string(), |temp| format!("{:.1}", temp));
+        write!(f, "Time: {} , {}Label: {} , Temperature: {:.1} , Max: {} , Critical: {}", self.datetime, host_label(&self.host),
+        self.label, self.temp, degrees(self.max), degrees(self.critical))?;
+        if self.near_critical {
+            write!(f, " , NEAR CRITICAL")?;
+        }
+        Ok(())
     }
 }
 
 impl Record for ComponentRecord {
 
-    fn write_to_db(&self, conn: Arc<Mutex<Connection>>) -> Result<()> {
-        let conn = conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO component (datetime, label, temp) VALUES (?1, ?2, ?3)", 
-        (&self.datetime, &self.label, &self.temp))?;
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO component (datetime, label, temp, max, critical, near_critical, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+        stmt.execute((&self.datetime, &self.label, &self.temp, &self.max, &self.critical, &self.near_critical, host_id))?;
         Ok(())
     }
 
     fn query() -> &'static str {
-        "SELECT datetime, label, temp FROM component"
+        "SELECT c.datetime, c.label, c.temp, c.max, c.critical, c.near_critical, h.hostname FROM component c LEFT JOIN host h ON h.id = c.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
     }
 
-    fn query_by_dt(start_dt: String, end_dt: String) -> String {
-        // no functionality currently needed for querying system records by datetime
-        format!("SELECT datetime, label, temp FROM component WHERE datetime BETWEEN '{}' AND '{}'", start_dt, end_dt)
-        
+    fn query_raw_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT c.datetime, c.label, c.temp, c.max, c.critical, c.near_critical, h.hostname FROM component c LEFT JOIN host h ON h.id = c.host_id
+        WHERE ", in_range!("c"), "
+        ORDER BY c.datetime, c.id"),
+        range_params(start, end, host))
+    }
+
+    // long ranges read the rollups: the average temperature, near_critical when any sample was
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (rollup::by_resolution(start, end, Self::query_raw_by_dt(start, end, host).0,
+            rollup_queries!("component", "r.series AS label,
+                MAX(CASE r.metric WHEN 'temp' THEN r.sum / r.count END) AS temp,
+                NULL AS max,
+                MAX(CASE r.metric WHEN 'critical' THEN r.last END) AS critical,
+                IFNULL(MAX(CASE r.metric WHEN 'near_critical' THEN CAST(r.max AS INTEGER) END), 0) AS near_critical")),
+        range_params(start, end, host))
     }
 
     fn from_row(row: &Row) -> Result<Self> {
@@ -93,55 +303,264 @@ impl Record for ComponentRecord {
             datetime: row.get(0)?,
             label: row.get(1)?,
             temp: row.get(2)?,
+            max: row.get(3)?,
+            critical: row.get(4)?,
+            near_critical: row.get(5)?,
+            host: row.get(6)?,
         })
     }
         
 }
 
 
+// A mounted filesystem. The details are None for disks recorded before they were tracked.
 struct DiskRecord {
-    datetime: String,
+    datetime: Timestamp,
+    host: Option<String>,
+    mount_point: Option<String>,
+    // device name
     name: String,
+    file_system: Option<String>,
+    kind: Option<String>,
+    removable: Option<bool>,
     total: u64,
-    available: u64
+    available: u64,
+    inodes: Option<u64>,
+    inodes_free: Option<u64>,
+    read_only: Option<bool>
 }
 
 impl fmt::Display for DiskRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Time: {} , Name: {} , Total: {} , Available: {}", self.datetime, self.name, self.total, self.available)
+        let unknown = "unknown".to_string();
+        let flag = |flag: Option<bool>| flag.map_or(unknown.clone(), |flag| flag.to_string());
+        let inodes = match (self.inodes_free, self.inodes) {
+            (Some(free), Some(total)) => format!("{} free of {}", free, total),
+            _ => unknown.clone(),
+        };
+        write!(f, "Time: {} , {}Mount: {} , Device: {} , Filesystem: {} , Kind: {} , Removable: {} , Read-only: {} , Total: {} , Available: {} , Inodes: {}",
+        self.datetime, host_label(&self.host), self.mount_point.as_ref().unwrap_or(&unknown), self.name,
+        self.file_system.as_ref().unwrap_or(&unknown), self.kind.as_ref().unwrap_or(&unknown),
+        flag(self.removable), flag(self.read_only), self.total, self.available, inodes)
     }
 }
 
 impl Record for DiskRecord {
 
-    fn write_to_db(&self, conn: Arc<Mutex<Connection>>) -> Result<()> {
-        let conn = conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO disk (datetime, name, total, available) VALUES (?1, ?2, ?3, ?4)", 
-        (&self.datetime, &self.name, &self.total, &self.available))?;
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO disk (datetime, mount_point, name, file_system, kind, removable, total, available,
+            inodes, inodes_free, read_only, host_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)")?;
+        stmt.execute((&self.datetime, &self.mount_point, &self.name, &self.file_system, &self.kind, &self.removable,
+            &self.total, &self.available, &self.inodes, &self.inodes_free, &self.read_only, host_id))?;
         Ok(())
     }
 
     fn query() -> &'static str {
-        "SELECT datetime, name, total, available FROM disk"
+        "SELECT d.datetime, d.mount_point, d.name, d.file_system, d.kind, d.removable, d.total, d.available,
+        d.inodes, d.inodes_free, d.read_only, h.hostname FROM disk d LEFT JOIN host h ON h.id = d.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
     }
     
-    fn query_by_dt(start_dt: String, end_dt: String) -> String {
-        format!("SELECT datetime, name, total, available FROM disk WHERE datetime BETWEEN '{}' AND '{}'", start_dt, end_dt)
+    fn query_raw_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT d.datetime, d.mount_point, d.name, d.file_system, d.kind, d.removable, d.total, d.available,
+        d.inodes, d.inodes_free, d.read_only, h.hostname FROM disk d LEFT JOIN host h ON h.id = d.host_id
+        WHERE ", in_range!("d"), "
+        ORDER BY d.datetime, d.id"),
+        range_params(start, end, host))
+    }
+
+    // long ranges read the rollups, which keep disks by mount point (the device name for disks recorded
+    // before mount points were) and stand that in for both
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (rollup::by_resolution(start, end, Self::query_raw_by_dt(start, end, host).0,
+            rollup_queries!("disk", "r.series AS mount_point, r.series AS name, NULL AS file_system, NULL AS kind, NULL AS removable,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'total' THEN r.sum / r.count END)) AS INTEGER) AS total,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'available' THEN r.sum / r.count END)) AS INTEGER) AS available,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'inodes' THEN r.sum / r.count END)) AS INTEGER) AS inodes,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'inodes_free' THEN r.sum / r.count END)) AS INTEGER) AS inodes_free,
+                NULL AS read_only")),
+        range_params(start, end, host))
     }
 
     fn from_row(row: &Row) -> Result<Self> {
         Ok(DiskRecord {
             datetime: row.get(0)?,
-            name: row.get(1)?,
-            total: row.get(2)?,
-            available: row.get(3)?,
+            mount_point: row.get(1)?,
+            name: row.get(2)?,
+            file_system: row.get(3)?,
+            kind: row.get(4)?,
+            removable: row.get(5)?,
+            total: row.get(6)?,
+            available: row.get(7)?,
+            inodes: row.get(8)?,
+            inodes_free: row.get(9)?,
+            read_only: row.get(10)?,
+            host: row.get(11)?,
+        })
+    }
+}
+
+// What statvfs tells about a mount that sysinfo does not
+struct MountStats {
+    // None for filesystems that report no inode count (btrfs, many network filesystems)
+    inodes: Option<u64>,
+    inodes_free: Option<u64>,
+    read_only: bool
+}
+
+fn mount_stats(mount_point: &Path) -> Option<MountStats> {
+    let path = CString::new(mount_point.as_os_str().as_bytes()).ok()?;
+    // SAFETY: path is nul terminated and stats is only read after statvfs filled it in
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    let inodes = Some(stats.f_files as u64).filter(|inodes| *inodes > 0);
+    Some(MountStats {
+        inodes,
+        inodes_free: inodes.map(|_| stats.f_ffree as u64),
+        read_only: stats.f_flag & libc::ST_RDONLY != 0,
+    })
+}
+
+// Mounts that appeared, disappeared or changed between read-only and read-write since the previous
+// sample, keyed by mount point. The read-only flag is None where statvfs failed, those are not
+// compared. The first sample only establishes what is mounted.
+fn mount_events(dt: Timestamp, current: &HashMap<String, Option<bool>>, previous: Option<&HashMap<String, Option<bool>>>) -> Vec<EventRecord> {
+    let Some(previous) = previous else {
+        return Vec::new();
+    };
+    let mut changes: Vec<(&String, String)> = Vec::new();
+    for (mount_point, read_only) in current {
+        match previous.get(mount_point) {
+            None => changes.push((mount_point, format!("Mounted {}", mount_point))),
+            Some(Some(was_read_only)) if read_only.is_some_and(|read_only| read_only != *was_read_only) => {
+                let mode = if *was_read_only { "read-write" } else { "read-only" };
+                changes.push((mount_point, format!("Remounted {} {}", mount_point, mode)));
+            }
+            Some(_) => {}
+        }
+    }
+    for mount_point in previous.keys().filter(|mount_point| !current.contains_key(*mount_point)) {
+        changes.push((mount_point, format!("Unmounted {}", mount_point)));
+    }
+    changes.sort();
+    changes.into_iter()
+        .map(|(_, detail)| EventRecord { datetime: dt, host: None, kind: "mount".to_string(), detail })
+        .collect()
+}
+
+// What a block device read and wrote in the seconds since the previous sample
+struct DiskIoRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    device: String,
+    seconds: f64,
+    reads: u64,
+    writes: u64,
+    read_bytes: u64,
+    write_bytes: u64
+}
+
+impl fmt::Display for DiskIoRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}Device: {} , Interval: {:.1}s , Reads: {} ({} bytes) , Writes: {} ({} bytes)",
+        self.datetime, host_label(&self.host), self.device, self.seconds, self.reads, self.read_bytes, self.writes, self.write_bytes)
+    }
+}
+
+impl Record for DiskIoRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO disk_io (datetime, device, seconds, reads, writes, read_bytes, write_bytes, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?;
+        stmt.execute((&self.datetime, &self.device, &self.seconds, &self.reads, &self.writes, &self.read_bytes, &self.write_bytes, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT i.datetime, i.device, i.seconds, i.reads, i.writes, i.read_bytes, i.write_bytes, h.hostname
+        FROM disk_io i LEFT JOIN host h ON h.id = i.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT i.datetime, i.device, i.seconds, i.reads, i.writes, i.read_bytes, i.write_bytes, h.hostname
+        FROM disk_io i LEFT JOIN host h ON h.id = i.host_id
+        WHERE ", in_range!("i"), "
+        ORDER BY i.datetime, i.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(DiskIoRecord {
+            datetime: row.get(0)?,
+            device: row.get(1)?,
+            seconds: row.get(2)?,
+            reads: row.get(3)?,
+            writes: row.get(4)?,
+            read_bytes: row.get(5)?,
+            write_bytes: row.get(6)?,
+            host: row.get(7)?,
         })
     }
 }
 
+// Counters of one line of /proc/diskstats
+#[derive(Clone, Copy, Default)]
+struct DiskStats {
+    reads: u64,
+    read_sectors: u64,
+    writes: u64,
+    write_sectors: u64
+}
+
+// the kernel counts diskstats sectors in 512 byte units whatever the device's real sector size
+const DISKSTATS_SECTOR: u64 = 512;
+
+fn parse_diskstats(contents: &str) -> HashMap<String, DiskStats> {
+    let mut stats = HashMap::new();
+    for line in contents.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            continue;
+        }
+        let field = |i: usize| fields[i].parse::<u64>().ok();
+        if let (Some(reads), Some(read_sectors), Some(writes), Some(write_sectors)) = (field(3), field(5), field(7), field(9)) {
+            stats.insert(fields[2].to_string(), DiskStats { reads, read_sectors, writes, write_sectors });
+        }
+    }
+    stats
+}
+
+// I/O of every device present in both samples. Devices that never did any I/O (unused loop and ram
+// devices) are left out, as are devices whose counters went back: diskstats counters are 64 bit and
+// don't wrap, so the device was replaced.
+fn disk_io_sample(dt: Timestamp, current: &HashMap<String, DiskStats>, previous: &HashMap<String, DiskStats>, seconds: f64) -> Vec<DiskIoRecord> {
+    let mut devices: Vec<&String> = current.keys().collect();
+    devices.sort();
+    devices.into_iter()
+        .filter_map(|device| {
+            let (now, then) = (&current[device], previous.get(device)?);
+            if now.reads == 0 && now.writes == 0 {
+                return None;
+            }
+            Some(DiskIoRecord {
+                datetime: dt,
+                host: None,
+                device: device.clone(),
+                seconds,
+                reads: now.reads.checked_sub(then.reads)?,
+                writes: now.writes.checked_sub(then.writes)?,
+                read_bytes: now.read_sectors.checked_sub(then.read_sectors)? * DISKSTATS_SECTOR,
+                write_bytes: now.write_sectors.checked_sub(then.write_sectors)? * DISKSTATS_SECTOR,
+            })
+        })
+        .collect()
+}
+
 struct RAMRecord {
-    datetime: String,
+    datetime: Timestamp,
+    host: Option<String>,
     total_memory: u64,
     used_memory: u64,
     total_swap: u64,
@@ -150,27 +569,39 @@ struct RAMRecord {
 
 impl fmt::Display for RAMRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Time: {} , Total Memory: {} , Used Memory: {} , Total Swap: {} , Used Swap: {}", 
-        self.datetime, self.total_memory, self.used_memory, self.total_swap, self.used_swap)
+        write!(f, "Time: {} , {}Total Memory: {} , Used Memory: {} , Total Swap: {} , Used Swap: {}", 
+        self.datetime, host_label(&self.host), self.total_memory, self.used_memory, self.total_swap, self.used_swap)
     }
 }
 
 impl Record for RAMRecord {
 
-    fn write_to_db(&self, conn: Arc<Mutex<Connection>>) -> Result<()> {
-        let conn = conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap) VALUES (?1, ?2, ?3, ?4, ?5)", 
-        (&self.datetime, &self.total_memory, &self.used_memory, &self.total_swap, &self.used_swap))?;
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap, host_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+        stmt.execute((&self.datetime, &self.total_memory, &self.used_memory, &self.total_swap, &self.used_swap, host_id))?;
         Ok(())
     }
 
     fn query() -> &'static str {
-        "SELECT datetime, total_memory, used_memory, total_swap, used_swap FROM ram"
+        "SELECT r.datetime, r.total_memory, r.used_memory, r.total_swap, r.used_swap, h.hostname FROM ram r LEFT JOIN host h ON h.id = r.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
     }
 
-    fn query_by_dt(start_dt: String, end_dt: String) -> String {
-        format!("SELECT datetime, total_memory, used_memory, total_swap, used_swap FROM ram WHERE datetime BETWEEN {} AND {}", start_dt, end_dt)
+    fn query_raw_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT r.datetime, r.total_memory, r.used_memory, r.total_swap, r.used_swap, h.hostname FROM ram r LEFT JOIN host h ON h.id = r.host_id
+        WHERE ", in_range!("r"), "
+        ORDER BY r.datetime, r.id"),
+        range_params(start, end, host))
+    }
+
+    // long ranges read the averages of the rollups
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (rollup::by_resolution(start, end, Self::query_raw_by_dt(start, end, host).0,
+            rollup_queries!("ram", "CAST(ROUND(MAX(CASE r.metric WHEN 'total_memory' THEN r.sum / r.count END)) AS INTEGER) AS total_memory,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'used_memory' THEN r.sum / r.count END)) AS INTEGER) AS used_memory,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'total_swap' THEN r.sum / r.count END)) AS INTEGER) AS total_swap,
+                CAST(ROUND(MAX(CASE r.metric WHEN 'used_swap' THEN r.sum / r.count END)) AS INTEGER) AS used_swap")),
+        range_params(start, end, host))
     }
 
     fn from_row(row: &Row) -> Result<Self> {
@@ -180,521 +611,3356 @@ impl Record for RAMRecord {
             used_memory: row.get(2)?,
             total_swap: row.get(3)?,
             used_swap: row.get(4)?,
+            host: row.get(5)?,
         })
     }
 }
 
-fn main() {
-
-    //establish connection to db and handle errors
-    let conn = match Connection::open("./data/sysinfo.db") {
-        Ok(conn) => conn,
-        Err(e) => {
-            println!("Connection failed. Make sure the db exists and the path is correct");
-            println!("{}", e);
-            return;
-        }
-    };
+// Usage of one core, or of the whole cpu when cpu is "total"
+struct CpuRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    cpu: String,
+    usage: f32,
+    frequency: u64
+}
 
-    let conn = Arc::new(Mutex::new(conn));
+impl fmt::Display for CpuRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}CPU: {} , Usage: {:.1}% , Frequency: {} MHz", self.datetime, host_label(&self.host), self.cpu, self.usage, self.frequency)
+    }
+}
 
-    let (tx, rx) = mpsc::channel();
+impl Record for CpuRecord {
 
-    // Start record function to listen when to record initalize by telling it not to record
-    tx.send(0).unwrap();
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO cpu (datetime, cpu, usage, frequency, host_id) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        stmt.execute((&self.datetime, &self.cpu, &self.usage, &self.frequency, host_id))?;
+        Ok(())
+    }
 
-    let conn_thread = conn.clone();
-    thread::spawn(move || {
-        create_schema(conn_thread.clone());
-        let mut sys = SystemData::new_all();
-        write_sysdata(&mut sys, conn_thread.clone());
-    
-        let mut recording = false;
-        let mut p = false;
+    fn query() -> &'static str {
+        "SELECT c.datetime, c.cpu, c.usage, c.frequency, h.hostname FROM cpu c LEFT JOIN host h ON h.id = c.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
 
-        loop {
-            match rx.try_recv() {
-                Ok(incoming) => {
-                    if incoming == 0 {
-                        recording = false;
-                        p = false;
-                        continue; 
-                    } else if incoming == 1 {
-                        recording = true; 
-                        p = false;
-                    } else {
-                        recording = true;
-                        p = true;
-                    }
-                }
-                Err(_) => {} 
-            }
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT c.datetime, c.cpu, c.usage, c.frequency, h.hostname FROM cpu c LEFT JOIN host h ON h.id = c.host_id
+        WHERE ", in_range!("c"), "
+        ORDER BY c.datetime, c.id"), range_params(start, end, host))
+    }
 
-            if recording {
-                write_all_records(&mut sys, conn_thread.clone(), p); // Example recording action
-                thread::sleep(Duration::from_secs(10));
-            }
-        }
-    });
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(CpuRecord {
+            datetime: row.get(0)?,
+            cpu: row.get(1)?,
+            usage: row.get(2)?,
+            frequency: row.get(3)?,
+            host: row.get(4)?,
+        })
+    }
+}
 
-    println!("Welcome to the sysinfo database!");
-    // Loop to handle user input
-    loop {
-        start_menu();
-        let input = read_string("");
-        
-        let input: u8 = match input.trim().parse() {
-            Ok(n) => n,
-            //TODO: add more useful error information by including an additional match statement for different types of errors
-            Err(_) => {
-                println!("Invalid input. Please enter a number in the range 1-5.");
-                continue;
-            }
-        };
+struct LoadRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    one: f64,
+    five: f64,
+    fifteen: f64,
+    // seconds since boot
+    uptime: u64,
+    boot_time: Timestamp
+}
 
-        match input {
-            1 => start_recording(tx.clone()),
-            2 => stop_recording(tx.clone()),
-            3 => view_records(conn.clone()),
-            4 => live_data_feed(tx.clone()),
-            5 => {
-                println!("Quitting Program...");
-                break;
+impl fmt::Display for LoadRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}Load: {:.2} {:.2} {:.2} , Uptime: {}s , Boot Time: {}",
+        self.datetime, host_label(&self.host), self.one, self.five, self.fifteen, self.uptime, self.boot_time)
+    }
+}
 
-            }
-            _ => println!("Invalid input. Please enter a number in the range 1-5.")
-        };
+impl Record for LoadRecord {
 
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO load (datetime, one, five, fifteen, uptime, boot_time, host_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+        stmt.execute((&self.datetime, &self.one, &self.five, &self.fifteen, &self.uptime, &self.boot_time, host_id))?;
+        Ok(())
     }
-}
 
-fn start_menu() {
+    fn query() -> &'static str {
+        "SELECT l.datetime, l.one, l.five, l.fifteen, l.uptime, l.boot_time, h.hostname FROM load l LEFT JOIN host h ON h.id = l.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
 
-    println!("Please select one of the options below by typing the respective number and pressing the 'Enter' key.");
-    println!("1.    Start recording");
-    println!("2.    Stop recording");
-    println!("3.    View records");
-    println!("4.    Live data feed");
-    println!("5.    Quit Program");
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT l.datetime, l.one, l.five, l.fifteen, l.uptime, l.boot_time, h.hostname FROM load l LEFT JOIN host h ON h.id = l.host_id
+        WHERE ", in_range!("l"), "
+        ORDER BY l.datetime, l.id"), range_params(start, end, host))
+    }
 
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(LoadRecord {
+            datetime: row.get(0)?,
+            one: row.get(1)?,
+            five: row.get(2)?,
+            fifteen: row.get(3)?,
+            uptime: row.get(4)?,
+            boot_time: row.get(5)?,
+            host: row.get(6)?,
+        })
+    }
 }
 
-fn view_records_menu() -> u8 {
-
-    println!("Please select which type of records to view:");
-    println!("1.    System Data");
-    println!("2.    Components");
-    println!("3.    Ram and Swap");
-    println!("4.    Disks");
-    println!("5.    Go back");
-    
-    let input = read_string("");
-    let input: u8 = match input.trim().parse() {
-        Ok(n) => n,
+// Pressure stall information of one resource. "some" is the share of time at least one task was
+// stalled on it, "full" the share all non-idle tasks were. The averages are percentages over 10, 60 and
+// 300 seconds, total and stalled are microseconds.
+struct PressureRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    resource: String,
+    kind: String,
+    avg10: f64,
+    avg60: f64,
+    avg300: f64,
+    total: u64,
+    // None for the first sample, there is nothing to compare with
+    stalled: Option<u64>
+}
 
-        Err(_) => {
-            println!("Invalid input. Please enter a number in the range 1-5.");
-            0
-        }
-    }; 
-    // return our input from user back to the view records function
-    input
+impl fmt::Display for PressureRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stalled = self.stalled.map_or("unknown".to_string(), |stalled| format!("{}us", stalled));
+        write!(f, "Time: {} , {}Resource: {} {} , Avg10: {:.2}% , Avg60: {:.2}% , Avg300: {:.2}% , Stalled: {}",
+        self.datetime, host_label(&self.host), self.resource, self.kind, self.avg10, self.avg60, self.avg300, stalled)
+    }
 }
 
-fn start_recording(tx: Sender<u8>) {
-    // Send message to start recording
-    if let Err(err) = tx.send(1) {
-        eprintln!("Failed to send message: {}", err);
-        return;
+impl Record for PressureRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO pressure (datetime, resource, kind, avg10, avg60, avg300, total, stalled, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?;
+        stmt.execute((&self.datetime, &self.resource, &self.kind, &self.avg10, &self.avg60, &self.avg300, &self.total, &self.stalled, host_id))?;
+        Ok(())
     }
-    println!("Starting recording... We will keep recording data for you until you stop recording");
-    println!("enter 'q' at any time to return to the main menu");
 
-    loop {
-        let input = read_string("");
+    fn query() -> &'static str {
+        "SELECT p.datetime, p.resource, p.kind, p.avg10, p.avg60, p.avg300, p.total, p.stalled, h.hostname
+        FROM pressure p LEFT JOIN host h ON h.id = p.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
 
-        let input: char = match input.trim().parse() {
-            Ok(c) => c,
-            Err(_) => {
-                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
-                continue;
-            }
-        };
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT p.datetime, p.resource, p.kind, p.avg10, p.avg60, p.avg300, p.total, p.stalled, h.hostname
+        FROM pressure p LEFT JOIN host h ON h.id = p.host_id
+        WHERE ", in_range!("p"), "
+        ORDER BY p.datetime, p.id"), range_params(start, end, host))
+    }
 
-        match input {
-            'q' => break,
-            _ => {
-                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
-                continue;
-            }
-        }
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(PressureRecord {
+            datetime: row.get(0)?,
+            resource: row.get(1)?,
+            kind: row.get(2)?,
+            avg10: row.get(3)?,
+            avg60: row.get(4)?,
+            avg300: row.get(5)?,
+            total: row.get(6)?,
+            stalled: row.get(7)?,
+            host: row.get(8)?,
+        })
     }
 }
 
-fn stop_recording(tx: Sender<u8>) {
-    tx.send(0).unwrap();
-    println!("Stopped recording...");
-    
+const PRESSURE_ROOT: &str = "/proc/pressure";
+const PRESSURE_RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+// Lines look like "some avg10=0.12 avg60=0.05 avg300=0.01 total=123456", lines that do not are skipped
+fn parse_pressure(dt: Timestamp, resource: &str, contents: &str) -> Vec<PressureRecord> {
+    contents.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let kind = fields.next()?;
+            let mut values = HashMap::new();
+            for field in fields {
+                let (key, value) = field.split_once('=')?;
+                values.insert(key, value);
+            }
+            Some(PressureRecord {
+                datetime: dt,
+                host: None,
+                resource: resource.to_string(),
+                kind: kind.to_string(),
+                avg10: values.get("avg10")?.parse().ok()?,
+                avg60: values.get("avg60")?.parse().ok()?,
+                avg300: values.get("avg300")?.parse().ok()?,
+                total: values.get("total")?.parse().ok()?,
+                stalled: None,
+            })
+        })
+        .collect()
 }
 
-fn view_records(conn: Arc<Mutex<Connection>>) {
+// Kernels built without PSI, or booted with psi=0, have no pressure files and give no records
+fn read_pressure(root: &Path, dt: Timestamp) -> Vec<PressureRecord> {
+    PRESSURE_RESOURCES.iter()
+        .filter_map(|resource| fs::read_to_string(root.join(resource)).ok().map(|contents| parse_pressure(dt, resource, &contents)))
+        .flatten()
+        .collect()
+}
 
-    loop {
-        let input = view_records_menu();
-        let conn_clone = conn.clone();
-        match input {
-            1 => {let _ = print_records(query_db_all::<SysRecord>(conn_clone));},
-            2 => {query_choice::<ComponentRecord>(conn_clone)}
-            3 => {query_choice::<RAMRecord>(conn_clone)}
-            4 => {query_choice::<DiskRecord>(conn_clone)}
-            5 => return,
-            _ => {
-                println!("Invalid input. Please enter a number 1-5.");
-                continue;
-            }
-        }
+// Resource usage of one cgroup v2 cgroup, path is relative to the cgroup root. Counters are cumulative,
+// cpu times in microseconds, and None when the cgroup's controller is not enabled. memory_max is also
+// None when there is no limit.
+struct CgroupRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    path: String,
+    memory_current: Option<u64>,
+    memory_max: Option<u64>,
+    cpu_usage: Option<u64>,
+    cpu_user: Option<u64>,
+    cpu_system: Option<u64>,
+    io_read_bytes: Option<u64>,
+    io_write_bytes: Option<u64>,
+    io_reads: Option<u64>,
+    io_writes: Option<u64>
+}
 
+impl fmt::Display for CgroupRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = |value: Option<u64>| value.map_or("-".to_string(), |value| value.to_string());
+        write!(f, "Time: {} , {}Cgroup: {} , Memory: {} / {} , CPU: {}us (user {}us, system {}us) , Read: {} bytes , Written: {} bytes",
+        self.datetime, host_label(&self.host), self.path, value(self.memory_current), self.memory_max.map_or("max".to_string(), |max| max.to_string()),
+        value(self.cpu_usage), value(self.cpu_user), value(self.cpu_system), value(self.io_read_bytes), value(self.io_write_bytes))
     }
-    
-
 }
 
-fn live_data_feed(tx: Sender<u8>) {
-    tx.send(2).unwrap();
-    println!("Starting live data feed...");
-    println!("Press 'q' then enter to return to main menu.");
-    loop {
-        let input = read_string("");
+impl Record for CgroupRecord {
 
-        let input: char = match input.trim().parse() {
-            Ok(c) => c,
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO cgroup (datetime, path, memory_current, memory_max, cpu_usage, cpu_user, cpu_system,
+            io_read_bytes, io_write_bytes, io_reads, io_writes, host_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)")?;
+        stmt.execute((&self.datetime, &self.path, &self.memory_current, &self.memory_max, &self.cpu_usage, &self.cpu_user, &self.cpu_system,
+            &self.io_read_bytes, &self.io_write_bytes, &self.io_reads, &self.io_writes, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT g.datetime, g.path, g.memory_current, g.memory_max, g.cpu_usage, g.cpu_user, g.cpu_system,
+        g.io_read_bytes, g.io_write_bytes, g.io_reads, g.io_writes, h.hostname FROM cgroup g LEFT JOIN host h ON h.id = g.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT g.datetime, g.path, g.memory_current, g.memory_max, g.cpu_usage, g.cpu_user, g.cpu_system,
+        g.io_read_bytes, g.io_write_bytes, g.io_reads, g.io_writes, h.hostname FROM cgroup g LEFT JOIN host h ON h.id = g.host_id
+        WHERE ", in_range!("g"), "
+        ORDER BY g.datetime, g.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(CgroupRecord {
+            datetime: row.get(0)?,
+            path: row.get(1)?,
+            memory_current: row.get(2)?,
+            memory_max: row.get(3)?,
+            cpu_usage: row.get(4)?,
+            cpu_user: row.get(5)?,
+            cpu_system: row.get(6)?,
+            io_read_bytes: row.get(7)?,
+            io_write_bytes: row.get(8)?,
+            io_reads: row.get(9)?,
+            io_writes: row.get(10)?,
+            host: row.get(11)?,
+        })
+    }
+}
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+// "key value" lines, as in cpu.stat
+fn cgroup_keyed(contents: &str) -> HashMap<&str, u64> {
+    contents.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key, value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+// io.stat has a line per device ("8:0 rbytes=1 wbytes=2 rios=3 wios=4 ..."), the cgroup's totals are
+// their sums: read bytes, written bytes, reads and writes
+fn cgroup_io(contents: &str) -> (u64, u64, u64, u64) {
+    let mut totals = (0, 0, 0, 0);
+    for field in contents.split_whitespace() {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        let value: u64 = value.parse().unwrap_or(0);
+        match key {
+            "rbytes" => totals.0 += value,
+            "wbytes" => totals.1 += value,
+            "rios" => totals.2 += value,
+            "wios" => totals.3 += value,
+            _ => {}
+        }
+    }
+    totals
+}
+
+fn read_cgroup(dir: &Path, dt: Timestamp, path: String) -> CgroupRecord {
+    let read = |file: &str| fs::read_to_string(dir.join(file)).ok();
+    let cpu = read("cpu.stat");
+    let cpu = cpu.as_deref().map(cgroup_keyed);
+    let io = read("io.stat").map(|contents| cgroup_io(&contents));
+    CgroupRecord {
+        datetime: dt,
+        host: None,
+        path,
+        memory_current: read("memory.current").and_then(|value| value.trim().parse().ok()),
+        // "max" when unlimited
+        memory_max: read("memory.max").and_then(|value| value.trim().parse().ok()),
+        cpu_usage: cpu.as_ref().and_then(|cpu| cpu.get("usage_usec").copied()),
+        cpu_user: cpu.as_ref().and_then(|cpu| cpu.get("user_usec").copied()),
+        cpu_system: cpu.as_ref().and_then(|cpu| cpu.get("system_usec").copied()),
+        io_read_bytes: io.map(|io| io.0),
+        io_write_bytes: io.map(|io| io.1),
+        io_reads: io.map(|io| io.2),
+        io_writes: io.map(|io| io.3),
+    }
+}
+
+// Every wanted cgroup below root, in path order. The root cgroup itself is the whole host and is left
+// to the other collectors. Gives nothing on hosts without a cgroup v2 hierarchy at root (cgroup v1 or
+// hybrid setups, where root has no cgroup.controllers).
+fn read_cgroups(root: &Path, dt: Timestamp, cgroups: &Cgroups) -> Vec<CgroupRecord> {
+    let mut records = Vec::new();
+    if !root.join("cgroup.controllers").is_file() {
+        return records;
+    }
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if !entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                continue;
+            }
+            let dir = entry.path();
+            let path = dir.strip_prefix(root).unwrap_or(&dir).to_string_lossy().to_string();
+            if cgroups.wanted(&path) {
+                records.push(read_cgroup(&dir, dt, path));
+            }
+            dirs.push(dir);
+        }
+    }
+    records.sort_by(|a, b| a.path.cmp(&b.path));
+    records
+}
+
+// Traffic of one network interface, the rates are per second since the previous sample
+struct NetworkRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    interface: String,
+    counters: NetworkCounters,
+    rates: NetworkRates
+}
+
+// Totals since the interface came up, as reported by the kernel
+#[derive(Clone, Copy, Default)]
+struct NetworkCounters {
+    received: u64,
+    transmitted: u64,
+    packets_received: u64,
+    packets_transmitted: u64,
+    errors_received: u64,
+    errors_transmitted: u64
+}
+
+// None where there is nothing to compare with or a counter was reset
+#[derive(Default)]
+struct NetworkRates {
+    received: Option<f64>,
+    transmitted: Option<f64>,
+    packets_received: Option<f64>,
+    packets_transmitted: Option<f64>,
+    errors_received: Option<f64>,
+    errors_transmitted: Option<f64>
+}
+
+impl NetworkRates {
+
+    fn between(previous: &NetworkCounters, current: &NetworkCounters, seconds: f64) -> Self {
+        NetworkRates {
+            received: counter_rate(previous.received, current.received, seconds),
+            transmitted: counter_rate(previous.transmitted, current.transmitted, seconds),
+            packets_received: counter_rate(previous.packets_received, current.packets_received, seconds),
+            packets_transmitted: counter_rate(previous.packets_transmitted, current.packets_transmitted, seconds),
+            errors_received: counter_rate(previous.errors_received, current.errors_received, seconds),
+            errors_transmitted: counter_rate(previous.errors_transmitted, current.errors_transmitted, seconds),
+        }
+    }
+}
+
+// Per second increase of a kernel counter between two samples. The interface counters sysinfo reads are
+// 64 bit and don't wrap, a counter that went back belongs to an interface that was reset or recreated
+// under the same name, and its rate is unknown.
+fn counter_rate(previous: u64, current: u64, seconds: f64) -> Option<f64> {
+    match current.checked_sub(previous) {
+        Some(increase) if seconds > 0.0 => Some(increase as f64 / seconds),
+        _ => None,
+    }
+}
+
+impl fmt::Display for NetworkRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rate = |rate: Option<f64>| rate.map_or("unknown".to_string(), |rate| format!("{:.0}/s", rate));
+        write!(f, "Time: {} , {}Interface: {} , Received: {} ({}) , Transmitted: {} ({}) , Packets: {} / {} , Errors: {} / {}",
+        self.datetime, host_label(&self.host), self.interface,
+        self.counters.received, rate(self.rates.received), self.counters.transmitted, rate(self.rates.transmitted),
+        self.counters.packets_received, self.counters.packets_transmitted, self.counters.errors_received, self.counters.errors_transmitted)
+    }
+}
+
+impl Record for NetworkRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO network (datetime, interface, received, transmitted, packets_received, packets_transmitted,
+            errors_received, errors_transmitted, received_rate, transmitted_rate, packets_received_rate, packets_transmitted_rate,
+            errors_received_rate, errors_transmitted_rate, host_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)")?;
+        let (counters, rates) = (&self.counters, &self.rates);
+        stmt.execute(rusqlite::params![&self.datetime, &self.interface,
+            counters.received, counters.transmitted, counters.packets_received, counters.packets_transmitted,
+            counters.errors_received, counters.errors_transmitted,
+            rates.received, rates.transmitted, rates.packets_received, rates.packets_transmitted,
+            rates.errors_received, rates.errors_transmitted, host_id])?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT n.datetime, n.interface, n.received, n.transmitted, n.packets_received, n.packets_transmitted, n.errors_received, n.errors_transmitted,
+            n.received_rate, n.transmitted_rate, n.packets_received_rate, n.packets_transmitted_rate, n.errors_received_rate, n.errors_transmitted_rate,
+            h.hostname FROM network n LEFT JOIN host h ON h.id = n.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT n.datetime, n.interface, n.received, n.transmitted, n.packets_received, n.packets_transmitted, n.errors_received, n.errors_transmitted,
+            n.received_rate, n.transmitted_rate, n.packets_received_rate, n.packets_transmitted_rate, n.errors_received_rate, n.errors_transmitted_rate,
+            h.hostname FROM network n LEFT JOIN host h ON h.id = n.host_id
+        WHERE ", in_range!("n"), "
+        ORDER BY n.datetime, n.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(NetworkRecord {
+            datetime: row.get(0)?,
+            interface: row.get(1)?,
+            counters: NetworkCounters {
+                received: row.get(2)?,
+                transmitted: row.get(3)?,
+                packets_received: row.get(4)?,
+                packets_transmitted: row.get(5)?,
+                errors_received: row.get(6)?,
+                errors_transmitted: row.get(7)?,
+            },
+            rates: NetworkRates {
+                received: row.get(8)?,
+                transmitted: row.get(9)?,
+                packets_received: row.get(10)?,
+                packets_transmitted: row.get(11)?,
+                errors_received: row.get(12)?,
+                errors_transmitted: row.get(13)?,
+            },
+            host: row.get(14)?,
+        })
+    }
+}
+
+// One of the top processes of a sample
+struct ProcessRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    pid: u32,
+    name: String,
+    cmd: String,
+    user: Option<String>,
+    memory: u64,
+    virtual_memory: u64,
+    cpu: f32,
+    start_time: Timestamp,
+    // comma separated rankings the process made the top of
+    rank: String
+}
+
+impl ProcessRecord {
+
+    fn rank_value(&self, rank: ProcessRank) -> f64 {
+        match rank {
+            ProcessRank::Cpu => self.cpu as f64,
+            ProcessRank::Memory => self.memory as f64,
+            ProcessRank::VirtualMemory => self.virtual_memory as f64,
+        }
+    }
+}
+
+impl fmt::Display for ProcessRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}PID: {} , Name: {} , User: {} , CPU: {:.1}% , Memory: {} , Virtual Memory: {} , Started: {} , Top: {} , Command: {}",
+        self.datetime, host_label(&self.host), self.pid, self.name, self.user.as_deref().unwrap_or("unknown"),
+        self.cpu, self.memory, self.virtual_memory, self.start_time, self.rank, self.cmd)
+    }
+}
+
+impl Record for ProcessRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO process (datetime, pid, name, cmd, user, memory, virtual_memory, cpu, start_time, rank, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)")?;
+        stmt.execute((&self.datetime, &self.pid, &self.name, &self.cmd, &self.user, &self.memory, &self.virtual_memory,
+            &self.cpu, &self.start_time, &self.rank, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT p.datetime, p.pid, p.name, p.cmd, p.user, p.memory, p.virtual_memory, p.cpu, p.start_time, p.rank, h.hostname
+        FROM process p LEFT JOIN host h ON h.id = p.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT p.datetime, p.pid, p.name, p.cmd, p.user, p.memory, p.virtual_memory, p.cpu, p.start_time, p.rank, h.hostname
+        FROM process p LEFT JOIN host h ON h.id = p.host_id
+        WHERE ", in_range!("p"), "
+        ORDER BY p.datetime, p.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(ProcessRecord {
+            datetime: row.get(0)?,
+            pid: row.get(1)?,
+            name: row.get(2)?,
+            cmd: row.get(3)?,
+            user: row.get(4)?,
+            memory: row.get(5)?,
+            virtual_memory: row.get(6)?,
+            cpu: row.get(7)?,
+            start_time: row.get(8)?,
+            rank: row.get(9)?,
+            host: row.get(10)?,
+        })
+    }
+}
+
+// A process that started or exited. Spawns are dated by the process start time, exits by the first
+// sample the process was missing from.
+struct ProcessEventRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    // "spawn" or "exit"
+    event: String,
+    pid: u32,
+    ppid: Option<u32>,
+    name: String,
+    cmd: String,
+    start_time: Timestamp,
+    // highest values seen in the samples the process was running in
+    peak_memory: u64,
+    peak_cpu: f32
+}
+
+impl fmt::Display for ProcessEventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}Process {} , PID: {} , Parent: {} , Name: {} , Started: {} , Peak Memory: {} , Peak CPU: {:.1}% , Command: {}",
+        self.datetime, host_label(&self.host), self.event, self.pid, self.ppid.map_or("none".to_string(), |ppid| ppid.to_string()),
+        self.name, self.start_time, self.peak_memory, self.peak_cpu, self.cmd)
+    }
+}
+
+impl Record for ProcessEventRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO process_event (datetime, event, pid, ppid, name, cmd, start_time, peak_memory, peak_cpu, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)")?;
+        stmt.execute((&self.datetime, &self.event, &self.pid, &self.ppid, &self.name, &self.cmd, &self.start_time,
+            &self.peak_memory, &self.peak_cpu, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT e.datetime, e.event, e.pid, e.ppid, e.name, e.cmd, e.start_time, e.peak_memory, e.peak_cpu, h.hostname
+        FROM process_event e LEFT JOIN host h ON h.id = e.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)
+        ORDER BY e.datetime, e.id"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT e.datetime, e.event, e.pid, e.ppid, e.name, e.cmd, e.start_time, e.peak_memory, e.peak_cpu, h.hostname
+        FROM process_event e LEFT JOIN host h ON h.id = e.host_id
+        WHERE ", in_range!("e"), "
+        ORDER BY e.datetime, e.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(ProcessEventRecord {
+            datetime: row.get(0)?,
+            event: row.get(1)?,
+            pid: row.get(2)?,
+            ppid: row.get(3)?,
+            name: row.get(4)?,
+            cmd: row.get(5)?,
+            start_time: row.get(6)?,
+            peak_memory: row.get(7)?,
+            peak_cpu: row.get(8)?,
+            host: row.get(9)?,
+        })
+    }
+}
+
+// How many login sessions were open at a sample, and by how many different users
+struct SessionRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    active: u64,
+    users: u64
+}
+
+impl fmt::Display for SessionRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}Sessions: {} , Users: {}", self.datetime, host_label(&self.host), self.active, self.users)
+    }
+}
+
+impl Record for SessionRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO session (datetime, active, users, host_id) VALUES (?1, ?2, ?3, ?4)")?;
+        stmt.execute((&self.datetime, &self.active, &self.users, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT s.datetime, s.active, s.users, h.hostname FROM session s LEFT JOIN host h ON h.id = s.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT s.datetime, s.active, s.users, h.hostname FROM session s LEFT JOIN host h ON h.id = s.host_id
+        WHERE ", in_range!("s"), "
+        ORDER BY s.datetime, s.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(SessionRecord {
+            datetime: row.get(0)?,
+            active: row.get(1)?,
+            users: row.get(2)?,
+            host: row.get(3)?,
+        })
+    }
+}
+
+// A login session that started or ended. Starts are dated by the login time, ends by the first sample
+// the session was missing from.
+struct SessionEventRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    // "start" or "end"
+    event: String,
+    user: String,
+    // None when the user is not known to this host (e.g. a network account that is gone)
+    uid: Option<u32>,
+    tty: String,
+    // None for local logins
+    remote_host: Option<String>,
+    pid: u32,
+    login_time: Timestamp
+}
+
+impl fmt::Display for SessionEventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}Session {} , User: {} , UID: {} , TTY: {} , From: {} , PID: {} , Login: {}",
+        self.datetime, host_label(&self.host), self.event, self.user, self.uid.map_or("unknown".to_string(), |uid| uid.to_string()),
+        self.tty, self.remote_host.as_deref().unwrap_or("local"), self.pid, self.login_time)
+    }
+}
+
+impl Record for SessionEventRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO session_event (datetime, event, user, uid, tty, remote_host, pid, login_time, host_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?;
+        stmt.execute((&self.datetime, &self.event, &self.user, &self.uid, &self.tty, &self.remote_host, &self.pid, &self.login_time, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT e.datetime, e.event, e.user, e.uid, e.tty, e.remote_host, e.pid, e.login_time, h.hostname
+        FROM session_event e LEFT JOIN host h ON h.id = e.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)
+        ORDER BY e.datetime, e.id"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT e.datetime, e.event, e.user, e.uid, e.tty, e.remote_host, e.pid, e.login_time, h.hostname
+        FROM session_event e LEFT JOIN host h ON h.id = e.host_id
+        WHERE ", in_range!("e"), "
+        ORDER BY e.datetime, e.id"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(SessionEventRecord {
+            datetime: row.get(0)?,
+            event: row.get(1)?,
+            user: row.get(2)?,
+            uid: row.get(3)?,
+            tty: row.get(4)?,
+            remote_host: row.get(5)?,
+            pid: row.get(6)?,
+            login_time: row.get(7)?,
+            host: row.get(8)?,
+        })
+    }
+}
+
+// A login session as utmp has it
+#[derive(Clone, PartialEq, Debug)]
+struct Session {
+    user: String,
+    tty: String,
+    remote_host: Option<String>,
+    pid: u32,
+    // seconds since the epoch
+    login_time: u64
+}
+
+impl Session {
+
+    fn event(&self, event: &str, dt: Timestamp, uid: Option<u32>) -> SessionEventRecord {
+        let login_time = Timestamp::from_secs(self.login_time);
+        SessionEventRecord {
+            datetime: if event == "start" { login_time } else { dt },
+            host: None,
+            event: event.to_string(),
+            user: self.user.clone(),
+            uid,
+            tty: self.tty.clone(),
+            remote_host: self.remote_host.clone(),
+            pid: self.pid,
+            login_time,
+        }
+    }
+}
+
+const UTMP_PATH: &str = "/var/run/utmp";
+
+// a fixed size, nul padded utmp field
+fn utmp_string(field: &[libc::c_char]) -> String {
+    let bytes: Vec<u8> = field.iter().take_while(|c| **c != 0).map(|c| *c as u8).collect();
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+// The user sessions in the contents of a utmp file, read as the struct utmpx of the C library the
+// program is built against, whose layout differs between architectures. None when the file isn't made
+// of whole records of that layout, rather than misreading it. Other entries (boot time, run level,
+// getty processes waiting for a login, closed sessions) are skipped.
+fn parse_utmp(bytes: &[u8]) -> Option<Vec<Session>> {
+    let record = mem::size_of::<libc::utmpx>();
+    if !bytes.len().is_multiple_of(record) {
+        return None;
+    }
+    let sessions = bytes.chunks_exact(record)
+        .filter_map(|chunk| {
+            // SAFETY: chunk is exactly one utmpx long and utmpx is plain integers and arrays, valid for
+            // any bytes
+            let entry: libc::utmpx = unsafe { ptr::read_unaligned(chunk.as_ptr() as *const libc::utmpx) };
+            if entry.ut_type != libc::USER_PROCESS {
+                return None;
+            }
+            let remote_host = utmp_string(&entry.ut_host);
+            Some(Session {
+                user: utmp_string(&entry.ut_user),
+                tty: utmp_string(&entry.ut_line),
+                remote_host: Some(remote_host).filter(|host| !host.is_empty()),
+                pid: entry.ut_pid as u32,
+                // 32 bit where utmp is shared with 32 bit programs, read unsigned it lasts past 2038
+                login_time: entry.ut_tv.tv_sec as u32 as u64,
+            })
+        })
+        .collect();
+    Some(sessions)
+}
+
+// A start event for every session that is new since the previous sample and an end event for every one
+// that is gone. Like processes, the first sample only establishes what is open.
+fn session_events(dt: Timestamp, previous: Option<&[Session]>, current: &[Session], uid: impl Fn(&str) -> Option<u32>) -> Vec<SessionEventRecord> {
+    let Some(previous) = previous else {
+        return Vec::new();
+    };
+    let mut events: Vec<SessionEventRecord> = current.iter()
+        .filter(|session| !previous.contains(session))
+        .map(|session| session.event("start", dt, uid(&session.user)))
+        .chain(previous.iter()
+            .filter(|session| !current.contains(session))
+            .map(|session| session.event("end", dt, uid(&session.user))))
+        .collect();
+    events.sort_by(|a, b| a.datetime.cmp(&b.datetime).then(a.tty.cmp(&b.tty)));
+    events
+}
+
+// Something that happened to a host, noticed by comparing a sample with the ones before it
+struct EventRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    kind: String,
+    detail: String
+}
+
+impl fmt::Display for EventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Time: {} , {}Event: {} , {}", self.datetime, host_label(&self.host), self.kind, self.detail)
+    }
+}
+
+impl Record for EventRecord {
+
+    fn insert(&self, conn: &Connection, host_id: i64) -> Result<()> {
+        let mut stmt = conn.prepare_cached("INSERT INTO event (datetime, kind, detail, host_id) VALUES (?1, ?2, ?3, ?4)")?;
+        stmt.execute((&self.datetime, &self.kind, &self.detail, host_id))?;
+        Ok(())
+    }
+
+    fn query() -> &'static str {
+        "SELECT e.datetime, e.kind, e.detail, h.hostname FROM event e LEFT JOIN host h ON h.id = e.host_id
+        WHERE (?1 IS NULL OR h.hostname = ?1)
+        ORDER BY e.datetime"
+    }
+
+    fn query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+        (concat!("SELECT e.datetime, e.kind, e.detail, h.hostname FROM event e LEFT JOIN host h ON h.id = e.host_id
+        WHERE ", in_range!("e"), "
+        ORDER BY e.datetime"), range_params(start, end, host))
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(EventRecord {
+            datetime: row.get(0)?,
+            kind: row.get(1)?,
+            detail: row.get(2)?,
+            host: row.get(3)?,
+        })
+    }
+}
+
+// One metric of a series (a mount point, a sensor, nothing for ram) over a minute, hour or day
+struct RollupRecord {
+    datetime: Timestamp,
+    host: Option<String>,
+    resolution: String,
+    source: String,
+    series: String,
+    metric: String,
+    min: f64,
+    max: f64,
+    avg: f64,
+    last: f64,
+    count: u64
+}
+
+impl fmt::Display for RollupRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let series = match self.series.is_empty() {
+            true => self.source.clone(),
+            false => format!("{} {}", self.source, self.series),
+        };
+        write!(f, "Time: {} , {}Resolution: {} , Series: {} , Metric: {} , Min: {} , Max: {} , Avg: {:.1} , Last: {} , Samples: {}",
+        self.datetime, host_label(&self.host), self.resolution, series, self.metric, self.min, self.max, self.avg, self.last, self.count)
+    }
+}
+
+// The rollups of one table with the raw columns of RollupRecord
+macro_rules! rollup_record_query {
+    ($table:literal, $resolution:literal, $filter:literal) => {
+        concat!("SELECT r.datetime, '", $resolution, "' AS resolution, r.source, r.series, r.metric, r.min, r.max, r.sum / r.count AS avg,
+        r.last, r.count, h.hostname FROM ", $table, " r LEFT JOIN host h ON h.id = r.host_id
+        WHERE ", $filter, "
+        ORDER BY r.datetime, r.source, r.series, r.metric")
+    };
+}
+
+// Every daily rollup, ?1 is the hostname to filter by or NULL for every host
+const ROLLUP_QUERY: &str = rollup_record_query!("rollup_1d", "1d", "(?1 IS NULL OR h.hostname = ?1)");
+
+// The rollups of the resolution a range is read at, minutes for ranges read raw
+fn rollup_query_by_dt(start: Timestamp, end: Timestamp, host: Option<&str>) -> (&'static str, Vec<Value>) {
+    // rollups always have a host, which sqlite can only tell when the filter leaves out the unattributed samples
+    let minutes = rollup_record_query!("rollup_1m", "1m", "r.host_id IN (SELECT id FROM host WHERE ?3 IS NULL OR hostname = ?3) AND r.datetime BETWEEN ?1 AND ?2");
+    (rollup::by_resolution(start, end, minutes, [minutes,
+        rollup_record_query!("rollup_1h", "1h", "r.host_id IN (SELECT id FROM host WHERE ?3 IS NULL OR hostname = ?3) AND r.datetime BETWEEN ?1 AND ?2"),
+        rollup_record_query!("rollup_1d", "1d", "r.host_id IN (SELECT id FROM host WHERE ?3 IS NULL OR hostname = ?3) AND r.datetime BETWEEN ?1 AND ?2")]),
+    range_params(start, end, host))
+}
+
+fn rollup_query(range: Option<(Timestamp, Timestamp)>, host: Option<&str>) -> (&'static str, Vec<Value>) {
+    match range {
+        Some((start, end)) => rollup_query_by_dt(start, end, host),
+        None => (ROLLUP_QUERY, vec![host_param(host)]),
+    }
+}
+
+// Rollups are derived from the samples by rollup::update and only ever read, so they are not a Record
+fn query_rollups(conn: Arc<Database>, range: Option<(Timestamp, Timestamp)>, host: Option<&str>) -> Result<Vec<RollupRecord>> {
+    let (sql, params) = rollup_query(range, host);
+    let conn = conn.reader()?;
+    let mut stmt = conn.prepare(sql)?;
+    let record_iter = stmt.query_map(params_from_iter(params), RollupRecord::from_row)?;
+
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+impl RollupRecord {
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(RollupRecord {
+            datetime: row.get(0)?,
+            resolution: row.get(1)?,
+            source: row.get(2)?,
+            series: row.get(3)?,
+            metric: row.get(4)?,
+            min: row.get(5)?,
+            max: row.get(6)?,
+            avg: row.get(7)?,
+            last: row.get(8)?,
+            count: row.get(9)?,
+            host: row.get(10)?,
+        })
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let command = cli.command.unwrap_or(Command::Interactive);
+    let interval = match command {
+        Command::Record { interval, .. } | Command::Live { interval } | Command::Daemon { interval, .. } => interval,
+        _ => None,
+    };
+    let pid_file = match &command {
+        Command::Daemon { pid_file, .. } => pid_file.clone(),
+        _ => None,
+    };
+    let overrides = Overrides {
+        config: cli.config,
+        database: cli.database,
+        interval,
+        collectors: cli.collectors,
+        pid_file,
+        timezone: cli.timezone,
+    };
+    let config = match Config::load(&overrides).and_then(|config| config.create_db_dir().map(|_| config)) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    timestamp::set_zone(config.output.timezone.clone());
+
+    //establish connection to db and handle errors
+    let conn = match Database::open(&config.database) {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let result: CommandResult = match command {
+        Command::Record { count, .. } => record(conn, &config, count, config.output.print_samples).map_err(Into::into),
+        Command::Query { kind, from, to, host, name, near_critical } => query_kind(conn, kind, from, to, host, name, near_critical),
+        Command::Live { .. } => record(conn, &config, None, true).map_err(Into::into),
+        Command::Hosts => print_records(query_hosts(conn, None)).map_err(Into::into),
+        Command::History { host } => print_history(conn, host.as_deref()).map_err(Into::into),
+        Command::Export { kind, from, to, host, output } => export_kind(conn, &config, kind, from, to, host, output),
+        Command::Daemon { .. } => daemon::run(conn, config, overrides).map_err(Into::into),
+        Command::Prune => prune(conn, &config).map_err(Into::into),
+        Command::Check { plans } => check(conn, plans).map_err(Into::into),
+        Command::Interactive => {
+            interactive(conn, config);
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn interactive(conn: Arc<Database>, config: Arc<Config>) {
+
+    let (tx, rx) = mpsc::channel();
+
+    let conn_thread = conn.clone();
+    let recorder = thread::spawn(move || {
+        let mut collector = Collector::new();
+        let host_id = match write_sysdata(&mut collector.sys, conn_thread.clone()) {
+            Ok(host_id) => host_id,
+            Err(e) => {
+                eprintln!("Could not register this host, recording is unavailable: {}", e);
+                return;
+            }
+        };
+
+        let mut scheduler = Scheduler::new(Duration::from_secs(config.interval));
+        let mut recording = false;
+        let mut p = false;
+        // retention is enforced while recording, like the record command and the daemon do
+        let mut maintenance: Option<Maintenance> = None;
+
+        loop {
+            // while paused there is nothing to schedule, so just block until told what to do
+            let wake = if recording {
+                scheduler.wait(&rx)
+            } else {
+                match rx.recv() {
+                    Ok(control) => Wake::Message(control),
+                    Err(_) => Wake::Disconnected,
+                }
+            };
+
+            match wake {
+                Wake::Tick { missed } => {
+                    // reported even when not printing live, a skipped sample is a gap in the recording
+                    if missed > 0 {
+                        eprintln!("Missed {} sample(s), the previous sample took longer than {}s", missed, config.interval);
+                    }
+                    write_all_records(&mut collector, conn_thread.clone(), host_id, &config, p).report(|e| eprintln!("{}", e));
+                }
+                Wake::Message(Control::Pause) => {
+                    recording = false;
+                    if let Some(maintenance) = maintenance.take() {
+                        maintenance.stop();
+                    }
+                }
+                Wake::Message(control @ (Control::Record | Control::Live)) => {
+                    p = matches!(control, Control::Live);
+                    if !recording {
+                        recording = true;
+                        scheduler.reset();
+                        maintenance = Maintenance::start(conn_thread.clone(), config.retention.clone(), daemon::log);
+                    }
+                }
+                Wake::Message(Control::Reload) => {}
+                Wake::Message(Control::Shutdown) | Wake::Disconnected => break,
+            }
+        }
+        if let Some(maintenance) = maintenance {
+            maintenance.stop();
+        }
+    });
+
+    println!("Welcome to the sysinfo database!");
+    // Loop to handle user input
+    loop {
+        start_menu();
+        let input = read_string("");
+        
+        let input: u8 = match input.trim().parse() {
+            Ok(n) => n,
+            //TODO: add more useful error information by including an additional match statement for different types of errors
+            Err(_) => {
+                println!("Invalid input. Please enter a number in the range 1-5.");
+                continue;
+            }
+        };
+
+        match input {
+            1 => start_recording(tx.clone()),
+            2 => stop_recording(tx.clone()),
+            3 => view_records(conn.clone()),
+            4 => live_data_feed(tx.clone()),
+            5 => {
+                println!("Quitting Program...");
+                // let a sample in progress finish before exiting
+                let _ = tx.send(Control::Shutdown);
+                let _ = recorder.join();
+                break;
+
+            }
+            _ => println!("Invalid input. Please enter a number in the range 1-5.")
+        };
+
+    }
+}
+
+fn record(conn: Arc<Database>, config: &Config, count: Option<u64>, p: bool) -> std::result::Result<(), String> {
+    // ctrl-c stops recording between samples rather than in the middle of one
+    let signals = daemon::listen_for_signals()?;
+    let mut collector = Collector::new();
+    let host_id = write_sysdata(&mut collector.sys, conn.clone()).map_err(|e| format!("Could not register this host: {}", e))?;
+    let maintenance = Maintenance::start(conn.clone(), config.retention.clone(), daemon::log);
+
+    let mut scheduler = Scheduler::new(Duration::from_secs(config.interval));
+    let mut taken = 0;
+    let mut failed = 0;
+    loop {
+        match scheduler.wait(&signals) {
+            Wake::Tick { missed } => {
+                if missed > 0 {
+                    eprintln!("Missed {} sample(s), the previous sample took longer than {}s", missed, config.interval);
+                }
+                let stats = write_all_records(&mut collector, conn.clone(), host_id, config, p);
+                stats.report(|e| eprintln!("{}", e));
+                failed += stats.failed;
+                taken += 1;
+                if count.is_some_and(|count| taken >= count) {
+                    break;
+                }
+            }
+            Wake::Message(Control::Shutdown) | Wake::Disconnected => break,
+            Wake::Message(_) => {}
+        }
+    }
+    if let Some(maintenance) = maintenance {
+        maintenance.stop();
+    }
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(format!("{} record(s) could not be written", failed)),
+    }
+}
+
+fn prune(conn: Arc<Database>, config: &Config) -> std::result::Result<(), String> {
+    if !config.retention.enabled() {
+        println!("No retention is configured, nothing to prune");
+        return Ok(());
+    }
+    retention::use_incremental_vacuum(conn.writer(), |message| println!("{}", message))
+        .map_err(|e| format!("Could not enable incremental vacuum: {}", e))?;
+    let stats = retention::prune(conn.writer(), &config.retention, Timestamp::now(), || false).map_err(|e| format!("Pruning failed: {}", e))?;
+    println!("{}", stats.summary());
+    Ok(())
+}
+
+// Explains the range queries of every time based kind, over spans read from the raw samples and from
+// each rollup, and fails when sqlite would answer any of them by reading a whole table
+fn check(conn: Arc<Database>, show_plans: bool) -> std::result::Result<(), String> {
+    let conn = conn.reader().map_err(|e| format!("Could not open a connection to check: {}", e))?;
+    let kinds = [
+        ("component", range_queries(ComponentRecord::query_by_dt)),
+        ("ram", range_queries(RAMRecord::query_by_dt)),
+        ("disk", range_queries(DiskRecord::query_by_dt)),
+        ("disk-io", range_queries(DiskIoRecord::query_by_dt)),
+        ("cpu", range_queries(CpuRecord::query_by_dt)),
+        ("load", range_queries(LoadRecord::query_by_dt)),
+        ("pressure", range_queries(PressureRecord::query_by_dt)),
+        ("cgroup", range_queries(CgroupRecord::query_by_dt)),
+        ("network", range_queries(NetworkRecord::query_by_dt)),
+        ("process", range_queries(ProcessRecord::query_by_dt)),
+        ("process-event", range_queries(ProcessEventRecord::query_by_dt)),
+        ("session", range_queries(SessionRecord::query_by_dt)),
+        ("session-event", range_queries(SessionEventRecord::query_by_dt)),
+        ("event", range_queries(EventRecord::query_by_dt)),
+        ("rollup", range_queries(rollup_query_by_dt)),
+    ];
+    let (mut checked, mut scanning) = (0, 0);
+    for (kind, queries) in kinds {
+        for (span, sql, params) in queries {
+            let plan = plan::explain(&conn, sql, &params).map_err(|e| format!("Could not explain a {} query: {}", kind, e))?;
+            let scans = plan::full_scans(&plan);
+            checked += 1;
+            let host = if params[2] == Value::Null { "every host" } else { "one host" };
+            if !scans.is_empty() {
+                scanning += 1;
+                println!("{} over {}, {}: {}", kind, span, host, scans.join(" , "));
+            } else if show_plans {
+                println!("{} over {}, {}: ok", kind, span, host);
+            }
+            if show_plans || !scans.is_empty() {
+                for step in &plan {
+                    println!("    {}", step);
+                }
+            }
+        }
+    }
+    if scanning > 0 {
+        return Err(format!("{} of {} range queries read a whole table", scanning, checked));
+    }
+    println!("All {} range queries search an index", checked);
+    Ok(())
+}
+
+// builds the SQL and parameters of a range query, like Record::query_by_dt
+type RangeQuery = fn(Timestamp, Timestamp, Option<&str>) -> (&'static str, Vec<Value>);
+
+// The distinct queries of a kind for ranges read raw and from each rollup, for every host and for one.
+// Each is labelled with the shortest span it is used for.
+fn range_queries(query_by_dt: RangeQuery) -> Vec<(&'static str, &'static str, Vec<Value>)> {
+    let end = Timestamp::now();
+    let mut queries: Vec<(&str, &str, Vec<Value>)> = Vec::new();
+    for (span, secs) in [("an hour", 3600), ("2 days", 2 * 86400), ("30 days", 30 * 86400), ("2 years", 2 * 366 * 86400)] {
+        for host in [None, Some("localhost")] {
+            let (sql, params) = query_by_dt(end.minus_secs(secs), end, host);
+            if !queries.iter().any(|(_, seen, seen_params)| *seen == sql && seen_params[2] == params[2]) {
+                queries.push((span, sql, params));
+            }
+        }
+    }
+    queries
+}
+
+fn query_kind(conn: Arc<Database>, kind: RecordKind, from: Option<String>, to: Option<String>, host: Option<String>, name: Option<String>,
+    near_critical: bool) -> CommandResult {
+    let range = parse_range(from, to)?;
+    match (kind, near_critical) {
+        (RecordKind::Component, true) => return Ok(print_records(query_near_critical(conn, range, host.as_deref()))?),
+        (_, true) => return Err("--near-critical only applies to component records".into()),
+        (_, false) => {}
+    }
+    match (kind, name) {
+        (RecordKind::Process, Some(name)) => return Ok(print_records(query_processes(conn, &name, range, host.as_deref()))?),
+        (RecordKind::Process, None) => {}
+        (_, Some(_)) => return Err("--name only applies to process records".into()),
+        (_, None) => {}
+    }
+    let printed = match kind {
+        RecordKind::Sys => print_records(query_records::<SysRecord>(conn, range, host.as_deref())),
+        RecordKind::Component => print_records(query_records::<ComponentRecord>(conn, range, host.as_deref())),
+        RecordKind::Ram => print_records(query_records::<RAMRecord>(conn, range, host.as_deref())),
+        RecordKind::Disk => print_records(query_records::<DiskRecord>(conn, range, host.as_deref())),
+        RecordKind::DiskIo => print_records(query_records::<DiskIoRecord>(conn, range, host.as_deref())),
+        RecordKind::Cpu => print_records(query_records::<CpuRecord>(conn, range, host.as_deref())),
+        RecordKind::Load => print_records(query_records::<LoadRecord>(conn, range, host.as_deref())),
+        RecordKind::Pressure => print_records(query_records::<PressureRecord>(conn, range, host.as_deref())),
+        RecordKind::Cgroup => print_records(query_records::<CgroupRecord>(conn, range, host.as_deref())),
+        RecordKind::Network => print_records(query_records::<NetworkRecord>(conn, range, host.as_deref())),
+        RecordKind::Process => print_records(query_records::<ProcessRecord>(conn, range, host.as_deref())),
+        RecordKind::ProcessEvent => print_records(query_records::<ProcessEventRecord>(conn, range, host.as_deref())),
+        RecordKind::Session => print_records(query_records::<SessionRecord>(conn, range, host.as_deref())),
+        RecordKind::SessionEvent => print_records(query_records::<SessionEventRecord>(conn, range, host.as_deref())),
+        RecordKind::Rollup => print_records(query_rollups(conn, range, host.as_deref())),
+        RecordKind::Event => print_records(query_records::<EventRecord>(conn, range, host.as_deref())),
+    };
+    Ok(printed?)
+}
+
+// A sensor without a critical temperature is never near it
+fn near_critical(temp: f32, critical: Option<f32>, margin: f32) -> bool {
+    critical.is_some_and(|critical| temp >= critical - margin)
+}
+
+// Temperatures that were within the critical margin, optionally restricted to a datetime range
+fn query_near_critical(conn: Arc<Database>, range: Option<(Timestamp, Timestamp)>, host: Option<&str>) -> Result<Vec<ComponentRecord>> {
+    let (from, to) = range.unzip();
+    let conn = conn.reader()?;
+    let mut stmt = conn.prepare("SELECT c.datetime, c.label, c.temp, c.max, c.critical, c.near_critical, h.hostname
+        FROM component c LEFT JOIN host h ON h.id = c.host_id
+        WHERE c.near_critical = 1 AND (?1 IS NULL OR c.datetime BETWEEN ?1 AND ?2) AND (?3 IS NULL OR h.hostname = ?3)
+        ORDER BY c.datetime")?;
+    let record_iter = stmt.query_map((from, to, host), ComponentRecord::from_row)?;
+
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+// Snapshots of the processes called name, optionally restricted to a datetime range
+fn query_processes(conn: Arc<Database>, name: &str, range: Option<(Timestamp, Timestamp)>, host: Option<&str>) -> Result<Vec<ProcessRecord>> {
+    let (from, to) = range.unzip();
+    let conn = conn.reader()?;
+    let mut stmt = conn.prepare("SELECT p.datetime, p.pid, p.name, p.cmd, p.user, p.memory, p.virtual_memory, p.cpu, p.start_time, p.rank, h.hostname
+        FROM process p LEFT JOIN host h ON h.id = p.host_id
+        WHERE p.name = ?1 AND (?2 IS NULL OR p.datetime BETWEEN ?2 AND ?3) AND (?4 IS NULL OR h.hostname = ?4)
+        ORDER BY p.datetime")?;
+    let record_iter = stmt.query_map((name, from, to, host), ProcessRecord::from_row)?;
+
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+fn query_records<T>(conn: Arc<Database>, range: Option<(Timestamp, Timestamp)>, host: Option<&str>) -> Result<Vec<T>>
+where
+    T: Record {
+    match range {
+        Some((start, end)) => query_by_dt::<T>(conn, start, end, host),
+        None => query_db_all::<T>(conn, host),
+    }
+}
+
+fn export_kind(conn: Arc<Database>, config: &Config, kind: RecordKind, from: Option<String>, to: Option<String>, host: Option<String>, output: Option<PathBuf>) -> CommandResult {
+    let range = parse_range(from, to)?;
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => match File::create(&path) {
+            Ok(file) => Box::new(file),
+            Err(e) => return Err(format!("Could not create '{}': {}", path.display(), e).into()),
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+    match kind {
+        RecordKind::Sys => export_csv::<SysRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Component => export_csv::<ComponentRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Ram => export_csv::<RAMRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Disk => export_csv::<DiskRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::DiskIo => export_csv::<DiskIoRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Cpu => export_csv::<CpuRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Load => export_csv::<LoadRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Pressure => export_csv::<PressureRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Cgroup => export_csv::<CgroupRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Network => export_csv::<NetworkRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Process => export_csv::<ProcessRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::ProcessEvent => export_csv::<ProcessEventRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Session => export_csv::<SessionRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::SessionEvent => export_csv::<SessionEventRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+        RecordKind::Rollup => {
+            let (sql, params) = rollup_query(range, host.as_deref());
+            write_csv(conn, sql, params, config.output.csv_header, &config.output.timezone, &mut out)
+        }
+        RecordKind::Event => export_csv::<EventRecord>(conn, range, host.as_deref(), config.output.csv_header, &config.output.timezone, &mut out),
+    }
+}
+
+// Exports the raw rows of the samples, however long the range, with the column names as header
+fn export_csv<T>(conn: Arc<Database>, range: Option<(Timestamp, Timestamp)>, host: Option<&str>, header_row: bool, zone: &Zone, out: &mut dyn Write) -> CommandResult
+where
+    T: Record {
+    let (sql, params) = match range {
+        Some((start, end)) => T::query_raw_by_dt(start, end, host),
+        None => (T::query(), vec![host_param(host)]),
+    };
+    write_csv(conn, sql, params, header_row, zone, out)
+}
+
+// Timestamps are written as datetimes in zone, like query shows them
+fn write_csv(conn: Arc<Database>, sql: &str, params: Vec<Value>, header_row: bool, zone: &Zone, out: &mut dyn Write) -> CommandResult {
+    let conn = conn.reader()?;
+    let mut stmt = conn.prepare(sql)?;
+    let header: Vec<String> = stmt.column_names().iter().map(|name| csv_field(name)).collect();
+    let columns = header.len();
+    // every timestamp column is declared DATETIME
+    let timestamps: Vec<bool> = stmt.columns().iter()
+        .map(|column| column.decl_type().is_some_and(|decl_type| decl_type.eq_ignore_ascii_case("DATETIME")))
+        .collect();
+
+    if header_row {
+        writeln!(out, "{}", header.join(","))?;
+    }
+    let mut rows = stmt.query(params_from_iter(params))?;
+    while let Some(row) = rows.next()? {
+        let mut fields = Vec::with_capacity(columns);
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            let field = match row.get::<_, Value>(i)? {
+                Value::Null => String::new(),
+                Value::Integer(_) if *timestamp => row.get::<_, Timestamp>(i)?.format(zone),
+                Value::Integer(n) => n.to_string(),
+                Value::Real(n) => n.to_string(),
+                Value::Text(text) => csv_field(&text),
+                Value::Blob(_) => String::new(),
+            };
+            fields.push(field);
+        }
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn start_menu() {
+
+    println!("Please select one of the options below by typing the respective number and pressing the 'Enter' key.");
+    println!("1.    Start recording");
+    println!("2.    Stop recording");
+    println!("3.    View records");
+    println!("4.    Live data feed");
+    println!("5.    Quit Program");
+
+}
+
+fn view_records_menu() -> u8 {
+
+    println!("Please select which type of records to view:");
+    println!("1.    System Data");
+    println!("2.    Components");
+    println!("3.    Ram and Swap");
+    println!("4.    Disks");
+    println!("5.    Disk I/O");
+    println!("6.    CPU");
+    println!("7.    Load and Uptime");
+    println!("8.    Pressure");
+    println!("9.    Cgroups");
+    println!("10.   Network");
+    println!("11.   Processes");
+    println!("12.   Process Starts and Exits");
+    println!("13.   Sessions");
+    println!("14.   Logins and Logouts");
+    println!("15.   Events");
+    println!("16.   Rollups");
+    println!("17.   Go back");
+    
+    let input = read_string("");
+    let input: u8 = match input.trim().parse() {
+        Ok(n) => n,
+
+        Err(_) => {
+            println!("Invalid input. Please enter a number in the range 1-17.");
+            0
+        }
+    }; 
+    // return our input from user back to the view records function
+    input
+}
+
+fn start_recording(tx: Sender<Control>) {
+    // Send message to start recording
+    if let Err(err) = tx.send(Control::Record) {
+        eprintln!("Failed to send message: {}", err);
+        return;
+    }
+    println!("Starting recording... We will keep recording data for you until you stop recording");
+    println!("enter 'q' at any time to return to the main menu");
+
+    loop {
+        let input = read_string("");
+
+        let input: char = match input.trim().parse() {
+            Ok(c) => c,
+            Err(_) => {
+                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
+                continue;
+            }
+        };
+
+        match input {
+            'q' => break,
+            _ => {
+                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
+                continue;
+            }
+        }
+    }
+}
+
+fn stop_recording(tx: Sender<Control>) {
+    tx.send(Control::Pause).unwrap();
+    println!("Stopped recording...");
+    
+}
+
+fn view_records(conn: Arc<Database>) {
+
+    loop {
+        let input = view_records_menu();
+        let conn_clone = conn.clone();
+        match input {
+            1 => {let _ = print_records(query_db_all::<SysRecord>(conn_clone, None));},
+            2 => {component_choice(conn_clone)}
+            3 => {query_choice::<RAMRecord>(conn_clone)}
+            4 => {query_choice::<DiskRecord>(conn_clone)}
+            5 => {query_choice::<DiskIoRecord>(conn_clone)}
+            6 => {query_choice::<CpuRecord>(conn_clone)}
+            7 => {query_choice::<LoadRecord>(conn_clone)}
+            8 => {query_choice::<PressureRecord>(conn_clone)}
+            9 => {query_choice::<CgroupRecord>(conn_clone)}
+            10 => {query_choice::<NetworkRecord>(conn_clone)}
+            11 => {process_choice(conn_clone)}
+            12 => {query_choice::<ProcessEventRecord>(conn_clone)}
+            13 => {query_choice::<SessionRecord>(conn_clone)}
+            14 => {query_choice::<SessionEventRecord>(conn_clone)}
+            15 => {query_choice::<EventRecord>(conn_clone)}
+            16 => {rollup_choice(conn_clone)}
+            17 => return,
+            _ => {
+                println!("Invalid input. Please enter a number 1-17.");
+                continue;
+            }
+        }
+
+    }
+    
+
+}
+
+fn live_data_feed(tx: Sender<Control>) {
+    tx.send(Control::Live).unwrap();
+    println!("Starting live data feed...");
+    println!("Press 'q' then enter to return to main menu.");
+    loop {
+        let input = read_string("");
+
+        let input: char = match input.trim().parse() {
+            Ok(c) => c,
+            Err(_) => {
+                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
+                continue;
+            }
+        };
+
+        match input {
+            'q' => {
+                tx.send(Control::Record).unwrap();
+                break
+            },
+            _ => {
+                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
+                continue;
+            }
+        }
+    }
+}
+
+// Registers this machine as a host and records its configuration, returns the host id samples are written with
+fn write_sysdata(sys: &mut SystemData, conn: Arc<Database>) -> Result<i64> {
+    // Refresh system data
+    sys.refresh_all();
+    let now = Timestamp::now();
+    // Create a new SysRecord with current system information
+    let unknown = || "unknown".to_string();
+    let sys_record = SysRecord{
+        os: SystemData::name().unwrap_or_else(unknown),
+        osversion: SystemData::os_version().unwrap_or_else(unknown),
+        hostname: SystemData::host_name().unwrap_or_else(unknown),
+        kernel_version: SystemData::kernel_version(),
+        arch: SystemData::cpu_arch(),
+        cpu_brand: sys.cpus().first().map(|cpu| cpu.brand().trim().to_string()),
+        cpu_cores: sys.physical_core_count().map(|cores| cores as u32),
+        boot_time: Some(Timestamp::from_secs(SystemData::boot_time())),
+        first_seen: Some(now),
+        last_seen: Some(now),
+    };
+    let conn = conn.writer().lock().unwrap();
+    let host_id = host_id(&conn, &sys_record.hostname)?;
+
+    match record_configuration(&conn, &sys_record, host_id) {
+        Ok(Configuration::First) => println!("System data written successfully."),
+        Ok(Configuration::Changed(changes)) => println!("System data changed for '{}': {}", sys_record.hostname, changes.join(" , ")),
+        Ok(Configuration::Unchanged) => println!("System data unchanged for '{}'.", sys_record.hostname),
+        Err(e) => println!("Error occurred while writing system data: {}", e),
+    }
+    Ok(host_id)
+}
+
+// How a host's configuration compares to the last one recorded for it
+enum Configuration {
+    First,
+    Changed(Vec<String>),
+    Unchanged,
+}
+
+// A changed configuration starts a new sys row, an unchanged one only moves last_seen of the current row.
+// The boot time is updated in place: a reboot alone is not a new configuration.
+fn record_configuration(conn: &Connection, sys_record: &SysRecord, host_id: i64) -> Result<Configuration> {
+    let latest = conn.query_row(
+        "SELECT os, osversion, hostname, kernel_version, arch, cpu_brand, cpu_cores, boot_time, first_seen, last_seen, id FROM sys
+        WHERE host_id = ?1 ORDER BY id DESC LIMIT 1",
+        [host_id], |row| Ok((SysRecord::from_row(row)?, row.get::<_, i64>(10)?))).optional()?;
+
+    let configuration = match latest {
+        None => Configuration::First,
+        Some((previous, id)) => {
+            let changes = sys_record.changes_from(&previous);
+            if changes.is_empty() {
+                conn.execute("UPDATE sys SET boot_time = ?1, last_seen = ?2 WHERE id = ?3",
+                    (&sys_record.boot_time, &sys_record.last_seen, id))?;
+                return Ok(Configuration::Unchanged);
+            }
+            Configuration::Changed(changes)
+        }
+    };
+    sys_record.insert(conn, host_id)?;
+    Ok(configuration)
+}
+
+// Each host's first configuration in full, followed by what changed in every later one
+fn configuration_timeline(records: &[SysRecord]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut previous: Option<&SysRecord> = None;
+    for record in records {
+        match previous.filter(|previous| previous.hostname == record.hostname) {
+            Some(previous) => lines.push(format!("Since: {} , Hostname: {} , Changed: {}",
+                record.first_seen.map_or("unknown".to_string(), |dt| dt.to_string()), record.hostname, record.changes_from(previous).join(" , "))),
+            None => lines.push(record.to_string()),
+        }
+        previous = Some(record);
+    }
+    lines
+}
+
+fn print_history(conn: Arc<Database>, host: Option<&str>) -> Result<()> {
+    for line in configuration_timeline(&query_db_all::<SysRecord>(conn, host)?) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+
+
+fn query_db_all<T>(conn: Arc<Database>, host: Option<&str>) -> Result<Vec<T>>
+where
+    T: Record {
+    let conn = conn.reader()?;
+    let mut stmt = conn.prepare(T::query())?;
+    let record_iter = stmt.query_map([host], |row| T::from_row(row))?;
+
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+
+    Ok(records)
+}
+
+
+fn print_records<T>(records: Result<Vec<T>>) -> Result<()>
+where
+    T: std::fmt::Display {
+    match records {
+        Ok(records) => {
+            for record in records {
+                println!("{}", record);
+            }
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn query_by_dt<T>(conn: Arc<Database>, start: Timestamp, end: Timestamp, host: Option<&str>) -> Result<Vec<T>>
+where 
+    T: Record {
+
+    let (sql, params) = T::query_by_dt(start, end, host);
+    let conn = conn.reader()?;
+    let mut stmt = conn.prepare(sql)?;
+    let record_iter = match stmt.query_map(params_from_iter(params), |row| T::from_row(row)) {
+        Ok(record_iter) => record_iter,
+        Err(e) => {
+            eprintln!("Failed to execute query: {}", e);
+            return Err(e);
+        }
+    };
+    
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+
+    Ok(records)
+
+
+}
+
+// Datetimes given on the command line are read in the display timezone, only well formed ones reach a query
+fn parse_datetime(dt: &str) -> std::result::Result<Timestamp, String> {
+    Timestamp::parse(dt, timestamp::zone())
+}
+
+fn parse_range(from: Option<String>, to: Option<String>) -> std::result::Result<Option<(Timestamp, Timestamp)>, String> {
+    match (from, to) {
+        (Some(start), Some(end)) => Ok(Some((parse_datetime(&start)?, parse_datetime(&end)?))),
+        _ => Ok(None),
+    }
+}
+
+fn query_choice<T>(conn: Arc<Database>) 
+where 
+    T: Record {
+    loop {
+        println!("Choose how to query Records:");
+        println!("1.    All Records");
+        println!("2.    By Date Time");
+        println!("3.    Go Back");
+        let input = read_string("");
+        let input: u8 = match input.trim().parse() {
+            Ok(i) => i,
             Err(_) => {
-                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
+                println!("Invlaid Input. Please enter a number.");
                 continue;
             }
         };
-
         match input {
-            'q' => {
-                tx.send(1).unwrap();
-                break
+            1 => {
+                let _ = print_records(query_db_all::<T>(conn.clone(), None));
+            },
+            2 => {
+                let Some((start, end)) = get_datetime_range() else {
+                    break;
+                };
+                let _ = print_records::<T>(query_by_dt::<T>(conn.clone(), start, end, None));
+
             },
+            3 => {
+                break;
+            }
             _ => {
-                println!("Invlaid Input. Please enter 'q' to exit to the main menu.");
+                println!("Please enter one of the options given.");
+                continue;
+            }
+        }
+    }
+
+
+}
+
+// Processes can also be looked up by name over a datetime range
+fn process_choice(conn: Arc<Database>) {
+    let name = read_string("Enter a process name, or leave empty for all processes:");
+    let name = name.trim();
+    if name.is_empty() {
+        return query_choice::<ProcessRecord>(conn);
+    }
+    let range = get_datetime_range();
+    if range.is_none() {
+        return;
+    }
+    let _ = print_records(query_processes(conn, name, range, None));
+}
+
+fn component_choice(conn: Arc<Database>) {
+    let answer = read_string("Only show temperatures near critical? (y/N):");
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return query_choice::<ComponentRecord>(conn);
+    }
+    let range = get_datetime_range();
+    if range.is_none() {
+        return;
+    }
+    let _ = print_records(query_near_critical(conn, range, None));
+}
+
+// Every daily rollup, or the rollups a range is read at
+fn rollup_choice(conn: Arc<Database>) {
+    let answer = read_string("Only show the rollups a datetime range is read at? (y/N):");
+    let range = match answer.trim().eq_ignore_ascii_case("y") {
+        true => match get_datetime_range() {
+            Some(range) => Some(range),
+            None => return,
+        },
+        false => None,
+    };
+    let _ = print_records(query_rollups(conn, range, None));
+}
+
+fn read_string(prompt: &str) -> String {
+
+    println!("{}", prompt);
+    let mut input: String = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read.");
+    input
+}
+
+fn parse_datetime_range(dt_range: String) -> std::result::Result<Vec<Timestamp>, String> {
+    let re = Regex::new(r"[0-9]{4}-[0-9]{2}-[0-9]{2} [0-9]{2}:[0-9]{2}:[0-9]{2}").unwrap();
+
+    re.find_iter(dt_range.as_str())
+        .map(|m| Timestamp::parse(m.as_str(), timestamp::zone()))
+        .collect()
+}
+
+fn get_datetime_range() -> Option<(Timestamp, Timestamp)> {
+    println!("press 'q' to quit at any time.");
+    loop {
+        let dt_range = read_string(&format!("Enter a date time range in {} (YYYY-MM-DD HH:MM:SS --- YYYY-MM-DD HH:MM:SS):\n", timestamp::zone()));
+        if dt_range.trim() == "q" {
+            return None
+        }
+        let dates = match parse_datetime_range(dt_range) {
+            Ok(dates) => dates,
+            Err(e) => {
+                println!("{}, please follow the format provided.", e);
+                continue;
+            }
+        };
+        match dates.len() {
+            0 => {
+                println!("No datetime range given, please follow the format provided.");
+                continue;
+            }
+            1 => {
+                println!("Only 1 datetime given, please give two datetimes to form a range you want to query.");
                 continue;
             }
+            2 => {
+                return Some((dates[0], dates[1]))
+            }
+            _ => {
+                println!("Too many datetimes provided. Please enter two datetimes to form a range you want to query.")
+            }
+        }
+        
+    }
+
+}
+
+// Records taken in one sampling tick
+struct Sample {
+    datetime: Timestamp,
+    ram: Option<RAMRecord>,
+    disks: Vec<DiskRecord>,
+    disk_io: Vec<DiskIoRecord>,
+    components: Vec<ComponentRecord>,
+    cpus: Vec<CpuRecord>,
+    load: Option<LoadRecord>,
+    pressure: Vec<PressureRecord>,
+    cgroups: Vec<CgroupRecord>,
+    networks: Vec<NetworkRecord>,
+    processes: Vec<ProcessRecord>,
+    process_events: Vec<ProcessEventRecord>,
+    session: Option<SessionRecord>,
+    session_events: Vec<SessionEventRecord>,
+    events: Vec<EventRecord>,
+}
+
+// Outcome of writing a sample, failed records are counted and their errors kept for reporting
+#[derive(Default)]
+struct WriteStats {
+    written: usize,
+    failed: usize,
+    errors: Vec<Error>,
+}
+
+impl WriteStats {
+
+    // hands every error to log, e.g. println or the daemon's timestamped log
+    fn report(&self, log: impl Fn(&str)) {
+        for e in &self.errors {
+            log(&format!("Failed to write record: {}", e));
+        }
+    }
+
+    fn add(&mut self, result: Result<()>) {
+        match result {
+            Ok(_) => self.written += 1,
+            Err(e) => {
+                self.failed += 1;
+                self.errors.push(e);
+            }
+        }
+    }
+}
+
+impl Sample {
+
+    fn len(&self) -> usize {
+        self.ram.iter().count() + self.disks.len() + self.disk_io.len() + self.components.len() + self.cpus.len() + self.load.iter().count() + self.pressure.len() + self.cgroups.len() + self.networks.len() + self.processes.len() + self.process_events.len() + self.session.iter().count() + self.session_events.len() + self.events.len()
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ram_record) = &self.ram {
+            writeln!(f, "{}", ram_record)?;
+        }
+        for disk_record in &self.disks {
+            writeln!(f, "{}", disk_record)?;
+        }
+        for disk_io_record in &self.disk_io {
+            writeln!(f, "{}", disk_io_record)?;
+        }
+        for component_record in &self.components {
+            writeln!(f, "{}", component_record)?;
+        }
+        for cpu_record in &self.cpus {
+            writeln!(f, "{}", cpu_record)?;
+        }
+        if let Some(load_record) = &self.load {
+            writeln!(f, "{}", load_record)?;
+        }
+        for pressure_record in &self.pressure {
+            writeln!(f, "{}", pressure_record)?;
+        }
+        for cgroup_record in &self.cgroups {
+            writeln!(f, "{}", cgroup_record)?;
+        }
+        for network_record in &self.networks {
+            writeln!(f, "{}", network_record)?;
+        }
+        for process_record in &self.processes {
+            writeln!(f, "{}", process_record)?;
+        }
+        for process_event_record in &self.process_events {
+            writeln!(f, "{}", process_event_record)?;
+        }
+        if let Some(session_record) = &self.session {
+            writeln!(f, "{}", session_record)?;
+        }
+        for session_event_record in &self.session_events {
+            writeln!(f, "{}", session_event_record)?;
+        }
+        for event_record in &self.events {
+            writeln!(f, "{}", event_record)?;
+        }
+        Ok(())
+    }
+}
+
+// Collection state kept from one tick to the next, for collectors that report changes since the previous sample
+struct Collector {
+    sys: SystemData,
+    networks: Networks,
+    users: Users,
+    // counters of the interfaces in the previous sample and when it was taken, None before the first one
+    network_counters: Option<(Instant, HashMap<String, NetworkCounters>)>,
+    // /proc/diskstats at the previous sample and when it was read, None before the first one
+    disk_stats: Option<(Instant, HashMap<String, DiskStats>)>,
+    // mount points of the previous sample and whether they were read-only, None before the first one
+    mounts: Option<HashMap<String, Option<bool>>>,
+    // cumulative stall time of each resource and kind at the previous sample
+    pressure_totals: HashMap<(String, String), u64>,
+    // sessions open at the previous sample, None before the first one
+    sessions: Option<Vec<Session>>,
+    // processes running at the previous sample, None before the first one
+    running: Option<HashMap<(u32, u64), TrackedProcess>>,
+}
+
+// A running process as the lifecycle collector knows it. A pid can be reused once its process exits, so
+// processes are told apart by pid and start time together.
+#[derive(Clone)]
+struct TrackedProcess {
+    pid: u32,
+    ppid: Option<u32>,
+    name: String,
+    cmd: String,
+    // seconds since the epoch
+    start_time: u64,
+    peak_memory: u64,
+    peak_cpu: f32
+}
+
+impl TrackedProcess {
+
+    fn event(&self, event: &str, dt: Timestamp) -> ProcessEventRecord {
+        let start_time = Timestamp::from_secs(self.start_time);
+        ProcessEventRecord {
+            datetime: if event == "spawn" { start_time } else { dt },
+            host: None,
+            event: event.to_string(),
+            pid: self.pid,
+            ppid: self.ppid,
+            name: self.name.clone(),
+            cmd: self.cmd.clone(),
+            start_time,
+            peak_memory: self.peak_memory,
+            peak_cpu: self.peak_cpu,
+        }
+    }
+}
+
+impl Collector {
+
+    fn new() -> Self {
+        Collector {
+            sys: SystemData::new_all(),
+            networks: Networks::new(),
+            users: Users::new(),
+            disk_stats: None,
+            mounts: None,
+            sessions: None,
+            pressure_totals: HashMap::new(),
+            running: None,
+            network_counters: None,
+        }
+    }
+
+    fn collect_pressure(&mut self, dt: Timestamp, sample: &mut Sample) {
+        let mut records = read_pressure(Path::new(PRESSURE_ROOT), dt);
+        for record in &mut records {
+            let key = (record.resource.clone(), record.kind.clone());
+            record.stalled = self.pressure_totals.get(&key).and_then(|previous| record.total.checked_sub(*previous));
+            self.pressure_totals.insert(key, record.total);
+        }
+        sample.pressure = records;
+    }
+
+    // utmp is missing in most containers, they simply record no sessions, as does a C library whose
+    // utmp layout doesn't match the file
+    fn collect_sessions(&mut self, dt: Timestamp, sample: &mut Sample) {
+        let Some(current) = fs::read(UTMP_PATH).ok().and_then(|bytes| parse_utmp(&bytes)) else {
+            return;
+        };
+        self.users.refresh_list();
+        let users = &self.users;
+        let uid = |name: &str| users.iter().find(|user| user.name() == name).map(|user| **user.id());
+        sample.session_events = session_events(dt, self.sessions.as_deref(), &current, uid);
+
+        let mut names: Vec<&str> = current.iter().map(|session| session.user.as_str()).collect();
+        names.sort();
+        names.dedup();
+        sample.session = Some(SessionRecord { datetime: dt, host: None, active: current.len() as u64, users: names.len() as u64 });
+        self.sessions = Some(current);
+    }
+
+    fn collect_mount_events(&mut self, dt: Timestamp, sample: &mut Sample) {
+        let current: HashMap<String, Option<bool>> = sample.disks.iter()
+            .filter_map(|disk| Some((disk.mount_point.clone()?, disk.read_only)))
+            .collect();
+        sample.events.extend(mount_events(dt, &current, self.mounts.as_ref()));
+        self.mounts = Some(current);
+    }
+
+    // block device I/O is only available on Linux, elsewhere there is simply none
+    fn collect_disk_io(&mut self, dt: Timestamp, sample: &mut Sample) {
+        let Ok(contents) = fs::read_to_string("/proc/diskstats") else {
+            return;
+        };
+        let now = Instant::now();
+        let current = parse_diskstats(&contents);
+        if let Some((then, previous)) = &self.disk_stats {
+            sample.disk_io = disk_io_sample(dt, &current, previous, (now - *then).as_secs_f64());
+        }
+        self.disk_stats = Some((now, current));
+    }
+
+    fn collect_networks(&mut self, dt: Timestamp, sample: &mut Sample) {
+        self.networks.refresh_list();
+        let now = Instant::now();
+        let counters: HashMap<String, NetworkCounters> = self.networks.iter()
+            .map(|(interface, data)| (interface.clone(), NetworkCounters {
+                received: data.total_received(),
+                transmitted: data.total_transmitted(),
+                packets_received: data.total_packets_received(),
+                packets_transmitted: data.total_packets_transmitted(),
+                errors_received: data.total_errors_on_received(),
+                errors_transmitted: data.total_errors_on_transmitted(),
+            }))
+            .collect();
+
+        let previous = self.network_counters.take();
+        let (networks, events) = match &previous {
+            Some((then, previous)) => network_sample(dt, &counters, Some(previous), (now - *then).as_secs_f64()),
+            None => network_sample(dt, &counters, None, 0.0),
+        };
+        sample.networks = networks;
+        sample.events.extend(events);
+        self.network_counters = Some((now, counters));
+    }
+}
+
+// Records for the current counters, with rates against the previous sample, and events for interfaces that
+// appeared or disappeared since. Interfaces missing from previous get no rates, so one that comes back is
+// not compared against counters from before it went away.
+fn network_sample(dt: Timestamp, current: &HashMap<String, NetworkCounters>, previous: Option<&HashMap<String, NetworkCounters>>,
+    seconds: f64) -> (Vec<NetworkRecord>, Vec<EventRecord>) {
+    let mut interfaces: Vec<&String> = current.keys().collect();
+    interfaces.sort();
+    let records = interfaces.into_iter()
+        .map(|interface| NetworkRecord {
+            datetime: dt,
+            host: None,
+            interface: interface.clone(),
+            counters: current[interface],
+            rates: previous.and_then(|previous| previous.get(interface))
+                .map(|previous| NetworkRates::between(previous, &current[interface], seconds))
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    // the first sample only establishes which interfaces exist
+    let mut changes: Vec<(&String, &str)> = match previous {
+        Some(previous) => current.keys().filter(|interface| !previous.contains_key(*interface)).map(|interface| (interface, "added"))
+            .chain(previous.keys().filter(|interface| !current.contains_key(*interface)).map(|interface| (interface, "removed")))
+            .collect(),
+        None => Vec::new(),
+    };
+    changes.sort();
+    let events = changes.into_iter()
+        .map(|(interface, change)| EventRecord {
+            datetime: dt,
+            host: None,
+            kind: "network".to_string(),
+            detail: format!("Interface {} {}", interface, change),
+        })
+        .collect();
+    (records, events)
+}
+
+fn collect_sample(collector: &mut Collector, config: &Config) -> Sample {
+
+    let collectors = &config.collectors;
+    let sys = &mut collector.sys;
+    sys.refresh_all();
+    let dt = Timestamp::now();
+
+    let mut sample = Sample { datetime: dt, ram: None, disks: Vec::new(), disk_io: Vec::new(), components: Vec::new(), cpus: Vec::new(), load: None,
+        pressure: Vec::new(), cgroups: Vec::new(), networks: Vec::new(), processes: Vec::new(),
+        process_events: Vec::new(), session: None, session_events: Vec::new(), events: Vec::new() };
+
+    if collectors.ram {
+        sample.ram = Some(RAMRecord {
+            datetime: dt,
+            host: None,
+            total_memory: sys.total_memory(),
+            used_memory: sys.used_memory(),
+            total_swap: sys.total_swap(),
+            used_swap: sys.used_swap()
+        });
+    }
+
+    if collectors.disk {
+        let disks = Disks::new_with_refreshed_list();
+        for disk in &disks {
+            let stats = mount_stats(disk.mount_point());
+            sample.disks.push(DiskRecord {
+                datetime: dt,
+                host: None,
+                mount_point: Some(disk.mount_point().to_string_lossy().to_string()),
+                name: disk.name().to_string_lossy().to_string(),
+                file_system: Some(disk.file_system().to_string_lossy().to_string()),
+                kind: Some(disk.kind().to_string()),
+                removable: Some(disk.is_removable()),
+                total: disk.total_space(),
+                available: disk.available_space(),
+                inodes: stats.as_ref().and_then(|stats| stats.inodes),
+                inodes_free: stats.as_ref().and_then(|stats| stats.inodes_free),
+                read_only: stats.map(|stats| stats.read_only)
+            });
+        }
+    }
+
+    if collectors.component {
+        let components = Components::new_with_refreshed_list();
+        for component in &components {
+            // sensors without a maximum report NaN
+            let max = Some(component.max()).filter(|max| max.is_finite());
+            let critical = component.critical().filter(|critical| critical.is_finite());
+            sample.components.push(ComponentRecord {
+                datetime: dt,
+                host: None,
+                label: component.label().to_string(),
+                temp: component.temperature(),
+                max,
+                critical,
+                near_critical: near_critical(component.temperature(), critical, config.components.critical_margin)
+            });
+        }
+    }
+
+    if collectors.cpu {
+        // usage is measured between two refreshes, so the first sample after starting reads close to 0
+        let cpus = sys.cpus();
+        let frequency = match cpus.len() {
+            0 => 0,
+            n => cpus.iter().map(|cpu| cpu.frequency()).sum::<u64>() / n as u64,
+        };
+        sample.cpus.push(CpuRecord {
+            datetime: dt,
+            host: None,
+            cpu: "total".to_string(),
+            usage: sys.global_cpu_info().cpu_usage(),
+            frequency
+        });
+        for cpu in cpus {
+            sample.cpus.push(CpuRecord {
+                datetime: dt,
+                host: None,
+                cpu: cpu.name().to_string(),
+                usage: cpu.cpu_usage(),
+                frequency: cpu.frequency()
+            });
+        }
+    }
+
+    if collectors.load {
+        let load = SystemData::load_average();
+        sample.load = Some(LoadRecord {
+            datetime: dt,
+            host: None,
+            one: load.one,
+            five: load.five,
+            fifteen: load.fifteen,
+            uptime: SystemData::uptime(),
+            boot_time: Timestamp::from_secs(SystemData::boot_time())
+        });
+    }
+
+    if collectors.pressure {
+        collector.collect_pressure(dt, &mut sample);
+    }
+
+    if collectors.cgroup {
+        sample.cgroups = read_cgroups(Path::new(CGROUP_ROOT), dt, &config.cgroups);
+    }
+
+    if collectors.disk {
+        collector.collect_mount_events(dt, &mut sample);
+        collector.collect_disk_io(dt, &mut sample);
+    }
+
+    if collectors.network {
+        collector.collect_networks(dt, &mut sample);
+    }
+
+    if collectors.lifecycle {
+        let current = collector.sys.processes().values()
+            .filter(|process| process.thread_kind().is_none())
+            .map(|process| TrackedProcess {
+                pid: process.pid().as_u32(),
+                ppid: process.parent().map(|ppid| ppid.as_u32()),
+                name: process.name().to_string(),
+                cmd: process.cmd().join(" "),
+                start_time: process.start_time(),
+                peak_memory: process.memory(),
+                peak_cpu: process.cpu_usage(),
+            })
+            .collect();
+        let (running, events) = track_processes(dt, collector.running.as_ref(), current);
+        collector.running = Some(running);
+        sample.process_events = events;
+    }
+
+    if collectors.session {
+        collector.collect_sessions(dt, &mut sample);
+    }
+
+    if collectors.process {
+        // users come and go rarely but the list is cheap to read, so it is kept current
+        collector.users.refresh_list();
+        let processes = collector.sys.processes().values()
+            // sysinfo lists the threads of a process alongside it
+            .filter(|process| process.thread_kind().is_none())
+            .map(|process| ProcessRecord {
+                datetime: dt,
+                host: None,
+                pid: process.pid().as_u32(),
+                name: process.name().to_string(),
+                cmd: process.cmd().join(" "),
+                user: process.user_id().and_then(|uid| collector.users.get_user_by_id(uid)).map(|user| user.name().to_string()),
+                memory: process.memory(),
+                virtual_memory: process.virtual_memory(),
+                cpu: process.cpu_usage(),
+                start_time: Timestamp::from_secs(process.start_time()),
+                rank: String::new(),
+            })
+            .collect();
+        sample.processes = top_processes(processes, &config.processes);
+    }
+
+    sample
+}
+
+// Compares the processes running now with the ones running at the previous sample. Returns what is
+// running now, with peaks carried over, and a spawn or exit event for every difference. There is nothing
+// to compare with at the first sample, so it produces no events. Processes that start and exit between
+// two samples are never seen.
+fn track_processes(dt: Timestamp, previous: Option<&HashMap<(u32, u64), TrackedProcess>>, current: Vec<TrackedProcess>)
+    -> (HashMap<(u32, u64), TrackedProcess>, Vec<ProcessEventRecord>) {
+    let mut running = HashMap::new();
+    let mut events = Vec::new();
+    for mut process in current {
+        let key = (process.pid, process.start_time);
+        match previous.and_then(|previous| previous.get(&key)) {
+            Some(seen) => {
+                process.peak_memory = process.peak_memory.max(seen.peak_memory);
+                process.peak_cpu = process.peak_cpu.max(seen.peak_cpu);
+            }
+            None if previous.is_some() => events.push(process.event("spawn", dt)),
+            None => {}
+        }
+        running.insert(key, process);
+    }
+    if let Some(previous) = previous {
+        events.extend(previous.iter()
+            .filter(|(key, _)| !running.contains_key(*key))
+            .map(|(_, process)| process.event("exit", dt)));
+    }
+    events.sort_by(|a, b| a.datetime.cmp(&b.datetime).then(a.pid.cmp(&b.pid)));
+    (running, events)
+}
+
+// The top processes by each ranking, in ranking order. A process at the top of several rankings is kept
+// once, with all of them in its rank.
+fn top_processes(processes: Vec<ProcessRecord>, options: &Processes) -> Vec<ProcessRecord> {
+    let mut kept: Vec<(usize, Vec<&str>)> = Vec::new();
+    for rank in &options.rank_by {
+        let mut order: Vec<usize> = (0..processes.len()).collect();
+        order.sort_by(|&a, &b| processes[b].rank_value(*rank).total_cmp(&processes[a].rank_value(*rank))
+            .then(processes[a].pid.cmp(&processes[b].pid)));
+        for i in order.into_iter().take(options.top) {
+            match kept.iter_mut().find(|(kept, _)| *kept == i) {
+                Some((_, ranks)) => ranks.push(rank.name()),
+                None => kept.push((i, vec![rank.name()])),
+            }
+        }
+    }
+
+    let mut processes: Vec<Option<ProcessRecord>> = processes.into_iter().map(Some).collect();
+    kept.into_iter()
+        .filter_map(|(i, ranks)| processes[i].take().map(|process| ProcessRecord { rank: ranks.join(","), ..process }))
+        .collect()
+}
+
+// Writes every record of a sample in one transaction. A record that fails to insert is counted and
+// skipped, an error beginning or committing the transaction loses the whole sample. The write lock is
+// taken at BEGIN, so waiting for another process holding it happens there under the busy timeout.
+fn write_sample(conn: &mut Connection, sample: &Sample, host_id: i64) -> Result<WriteStats> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let mut stats = WriteStats::default();
+
+    if let Some(ram_record) = &sample.ram {
+        stats.add(ram_record.insert(&tx, host_id));
+    }
+    for disk_record in &sample.disks {
+        stats.add(disk_record.insert(&tx, host_id));
+    }
+    for disk_io_record in &sample.disk_io {
+        stats.add(disk_io_record.insert(&tx, host_id));
+    }
+    for component_record in &sample.components {
+        stats.add(component_record.insert(&tx, host_id));
+    }
+    for cpu_record in &sample.cpus {
+        stats.add(cpu_record.insert(&tx, host_id));
+    }
+    if let Some(load_record) = &sample.load {
+        // compared with the previous load sample, so this has to happen before inserting the new one
+        match reboot_event(&tx, host_id, load_record) {
+            Ok(Some(event)) => stats.add(event.insert(&tx, host_id)),
+            Ok(None) => {}
+            Err(e) => stats.errors.push(e),
         }
+        stats.add(load_record.insert(&tx, host_id));
+    }
+    for pressure_record in &sample.pressure {
+        stats.add(pressure_record.insert(&tx, host_id));
+    }
+    for cgroup_record in &sample.cgroups {
+        stats.add(cgroup_record.insert(&tx, host_id));
+    }
+    for network_record in &sample.networks {
+        stats.add(network_record.insert(&tx, host_id));
+    }
+    for process_record in &sample.processes {
+        stats.add(process_record.insert(&tx, host_id));
+    }
+    for process_event_record in &sample.process_events {
+        stats.add(process_event_record.insert(&tx, host_id));
+    }
+    if let Some(session_record) = &sample.session {
+        stats.add(session_record.insert(&tx, host_id));
+    }
+    for session_event_record in &sample.session_events {
+        stats.add(session_event_record.insert(&tx, host_id));
+    }
+    for event_record in &sample.events {
+        stats.add(event_record.insert(&tx, host_id));
+    }
+    // derived from the rows just written rather than a record of its own, like last_seen below. A failed
+    // update takes back what it added, the rest of the sample is still committed
+    if let Err(e) = rollup::update(&tx, rollup::BATCH) {
+        stats.errors.push(e);
+    }
+    // the host's current configuration was still in place at this sample. Not a record of its own,
+    // so a failure is reported without being counted
+    let seen = tx.prepare_cached("UPDATE sys SET last_seen = ?1 WHERE id = (SELECT MAX(id) FROM sys WHERE host_id = ?2)")
+        .and_then(|mut stmt| stmt.execute((&sample.datetime, host_id)));
+    if let Err(e) = seen {
+        stats.errors.push(e);
+    }
+
+    tx.commit()?;
+    Ok(stats)
+}
+
+// A boot time after the host's previous load sample means it rebooted since then. Comparing against the
+// sample rather than the previous boot time keeps small differences in how the boot time is derived from
+// being mistaken for reboots.
+fn reboot_event(conn: &Connection, host_id: i64, load: &LoadRecord) -> Result<Option<EventRecord>> {
+    let mut stmt = conn.prepare_cached("SELECT datetime, boot_time FROM load WHERE host_id = ?1 ORDER BY id DESC LIMIT 1")?;
+    let previous: Option<(Timestamp, Timestamp)> = stmt.query_row([host_id], |row| Ok((row.get(0)?, row.get(1)?))).optional()?;
+    Ok(match previous {
+        Some((last_sample, previous_boot)) if load.boot_time > last_sample => Some(EventRecord {
+            datetime: load.boot_time,
+            host: None,
+            kind: "reboot".to_string(),
+            detail: format!("Previous Boot: {} , Last Sample Before: {}", previous_boot, last_sample),
+        }),
+        _ => None,
+    })
+}
+
+fn write_all_records(collector: &mut Collector, conn: Arc<Database>, host_id: i64, config: &Config, p: bool) -> WriteStats {
+    // sample before taking the writer so pruning is only held up for the write itself
+    let sample = collect_sample(collector, config);
+    if p {
+        print!("{}", sample);
+    }
+    let mut conn = conn.writer().lock().unwrap();
+    match write_sample(&mut conn, &sample, host_id) {
+        Ok(stats) => stats,
+        Err(e) => WriteStats { written: 0, failed: sample.len(), errors: vec![e] },
     }
 }
 
-fn create_schema(conn: Arc<Mutex<Connection>>) {
-    let conn = conn.lock().unwrap();
-    match conn.execute(
-        "CREATE TABLE IF NOT EXISTS component (
-                id INTEGER PRIMARY KEY,
-                datetime DATETIME NOT NULL,
-                label TEXT NOT NULL,
-                temp INTEGER NOT NULL
-                )",
-        ()
-    ) {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Creating table 'component' failed...");
-            println!("{}", e);
-            return;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(dt: &str) -> Timestamp {
+        Timestamp::parse(dt, &timestamp::Zone::Utc).unwrap()
+    }
+
+    fn test_conn() -> Arc<Database> {
+        Arc::new(Database::open_in_memory())
+    }
+
+    fn seed(conn: Arc<Database>) {
+        seed_host(conn, "alpha");
+    }
+
+    fn seed_host(conn: Arc<Database>, hostname: &str) -> i64 {
+        let host_id = host_id(&conn.writer().lock().unwrap(), hostname).unwrap();
+        for (i, dt) in ["2024-01-01 00:00:00", "2024-01-01 12:00:00", "2024-01-02 00:00:00", "2024-01-03 00:00:00"].iter().enumerate() {
+            let i = i as u64;
+            component(dt, &format!("cpu{}", i), 40.0 + i as f32)
+                .write_to_db(conn.clone(), host_id).unwrap();
+            disk(dt, &format!("sda{}", i), 1000, 100 * i).write_to_db(conn.clone(), host_id).unwrap();
+            RAMRecord { datetime: ts(dt), host: None, total_memory: 8000, used_memory: 1000 * i, total_swap: 2000, used_swap: i }
+                .write_to_db(conn.clone(), host_id).unwrap();
+            CpuRecord { datetime: ts(dt), host: None, cpu: "total".to_string(), usage: 10.0 * i as f32, frequency: 2000 + i }
+                .write_to_db(conn.clone(), host_id).unwrap();
         }
+        host_id
     }
 
-    match conn.execute(
-        "CREATE TABLE IF NOT EXISTS disk (
-                id INTEGER PRIMARY KEY,
-                datetime DATETIME NOT NULL,
-                name TEXT NOT NULL,
-                total INTEGER NOT NULL,
-                available INTEGER NOT NULL
-                )",
-        ()
-    ) {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Creating table 'disk' failed");
-            println!("{}", e);
-        }
-    }
-
-    match conn.execute(
-        "CREATE TABLE IF NOT EXISTS ram (
-                id INTEGER PRIMARY KEY,
-                datetime DATETIME NOT NULL,
-                total_memory INTEGER NOT NULL,
-                used_memory INTEGER NOT NULL,
-                total_swap INTEGER NOT NULL,
-                used_swap INTEGER NOT NULL
-                )",
-        ()
-    ) {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Creating table 'ram' failed");
-            println!("{}", e);
+    fn component(dt: &str, label: &str, temp: f32) -> ComponentRecord {
+        ComponentRecord { datetime: ts(dt), host: None, label: label.to_string(), temp, max: Some(temp), critical: Some(100.0),
+            near_critical: near_critical(temp, Some(100.0), 5.0) }
+    }
+
+    fn disk(dt: &str, name: &str, total: u64, available: u64) -> DiskRecord {
+        DiskRecord { datetime: ts(dt), host: None, mount_point: Some(format!("/mnt/{}", name)), name: name.to_string(),
+            file_system: Some("ext4".to_string()), kind: Some("SSD".to_string()), removable: Some(false), total, available,
+            inodes: Some(1000), inodes_free: Some(900), read_only: Some(false) }
+    }
+
+    fn range<T: Record>(conn: Arc<Database>, start: &str, end: &str) -> Result<Vec<T>> {
+        query_by_dt::<T>(conn, ts(start), ts(end), None)
+    }
+
+    #[test]
+    fn component_range_is_inclusive() {
+        let conn = test_conn();
+        seed(conn.clone());
+        let records = range::<ComponentRecord>(conn, "2024-01-01 12:00:00", "2024-01-02 00:00:00").unwrap();
+        let labels: Vec<&str> = records.iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, vec!["cpu1", "cpu2"]);
+    }
+
+    #[test]
+    fn disk_range_is_inclusive() {
+        let conn = test_conn();
+        seed(conn.clone());
+        let records = range::<DiskRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 12:00:00").unwrap();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["sda0", "sda1"]);
+    }
+
+    #[test]
+    fn ram_range_is_inclusive() {
+        let conn = test_conn();
+        seed(conn.clone());
+        let records = range::<RAMRecord>(conn, "2024-01-02 00:00:00", "2024-01-03 00:00:00").unwrap();
+        let used: Vec<u64> = records.iter().map(|r| r.used_memory).collect();
+        assert_eq!(used, vec![2000, 3000]);
+    }
+
+    #[test]
+    fn cpu_range_is_inclusive() {
+        let conn = test_conn();
+        seed(conn.clone());
+        let records = range::<CpuRecord>(conn, "2024-01-01 00:00:00", "2024-01-02 00:00:00").unwrap();
+        let frequencies: Vec<u64> = records.iter().map(|r| r.frequency).collect();
+        assert_eq!(frequencies, vec![2000, 2001, 2002]);
+    }
+
+    #[test]
+    fn empty_range_returns_no_rows() {
+        let conn = test_conn();
+        seed(conn.clone());
+        assert!(range::<RAMRecord>(conn.clone(), "2023-01-01 00:00:00", "2023-12-31 23:59:59").unwrap().is_empty());
+        assert!(range::<DiskRecord>(conn, "2024-01-03 00:00:00", "2024-01-01 00:00:00").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malicious_input_is_rejected() {
+        let conn = test_conn();
+        seed(conn.clone());
+        let attacks = [
+            "2024-01-01 00:00:00' OR '1'='1",
+            "2024-01-01 00:00:00'; DROP TABLE ram; --",
+            "0 OR 1=1",
+            "",
+        ];
+        let given = |dt: &str| Some(dt.to_string());
+        for attack in attacks {
+            assert!(parse_range(given(attack), given("2024-01-03 00:00:00")).is_err());
+            assert!(parse_range(given("2024-01-01 00:00:00"), given(attack)).is_err());
+            assert!(query_kind(conn.clone(), RecordKind::Ram, given(attack), given(attack), None, None, false).is_err());
         }
+        // tables are untouched
+        assert_eq!(query_db_all::<RAMRecord>(conn.clone(), None).unwrap().len(), 4);
+        assert_eq!(query_db_all::<ComponentRecord>(conn, None).unwrap().len(), 4);
     }
 
-    match conn.execute(
-        "CREATE TABLE IF NOT EXISTS sys (
-                id INTEGER PRIMARY KEY,
-                os TEXT NOT NULL,
-                osversion TEXT NOT NULL,
-                hostname TEXT NOT NULL
-                )",
-        ()
-    ) {
-        Ok(_) => {},
-        Err(e) => {
-            println!("Creating 'sys' table failed");
-            println!("{}", e);
+    #[test]
+    fn range_values_are_bound_not_interpolated() {
+        let (start, end) = (ts("2024-01-01 00:00:00"), ts("2024-01-01 12:00:00"));
+        let (sql, params) = ComponentRecord::query_by_dt(start, end, Some("c' OR '1'='1"));
+        assert!(!sql.contains("OR '1'='1"));
+        assert_eq!(params, vec![Value::Integer(start.millis()), Value::Integer(end.millis()), Value::Text("c' OR '1'='1".to_string())]);
+        for sql in [DiskRecord::query_by_dt(start, end, None).0, RAMRecord::query_by_dt(start, end, None).0, CpuRecord::query_by_dt(start, end, None).0] {
+            assert!(sql.contains("hostname = ?3) AND") && sql.contains(".datetime BETWEEN ?1 AND ?2"));
         }
     }
-}
 
-fn write_sysdata(sys: &mut SystemData, conn: Arc<Mutex<Connection>>) {
-    // Refresh system data
-    sys.refresh_all();
-    // Create a new SysRecord with current system information
-    let sys_record = SysRecord{
-        os: SystemData::name().unwrap(),
-        osversion: SystemData::os_version().unwrap(),
-        hostname: SystemData::host_name().unwrap(),
-    };
-    // Query existing SysRecords from the database
-    let old_records_result = query_db_all::<SysRecord>(conn.clone());
-    match old_records_result {
-        Ok(old_records) => {
-            if old_records.is_empty() {
-                // If there are no old records, write the current sys_record to the database
-                match sys_record.write_to_db(conn.clone()) {
-                    Ok(_) => println!("System data written successfully."),
-                    Err(e) => println!("Error occurred while writing system data: {}", e),
-                }
-            } else {
-                // Check if the current hostname already exists in the old records
-                let mut hostname_exists = false;
-                for record in &old_records {
-                    if record.hostname == sys_record.hostname {
-                        hostname_exists = true;
-                        println!("System data not written: record for '{}' already exists.", sys_record.hostname);
-                        break;
-                    }
-                }
+    #[test]
+    fn export_writes_header_and_range_rows() {
+        let conn = test_conn();
+        seed(conn.clone());
+        disk("2024-01-01 06:00:00", "odd, \"name\"", 1, 0).write_to_db(conn.clone(), 1).unwrap();
+        let mut out = Vec::new();
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        export_csv::<DiskRecord>(conn, Some((ts("2024-01-01 00:00:00"), ts("2024-01-01 06:00:00"))), None, true, &berlin, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "datetime,mount_point,name,file_system,kind,removable,total,available,inodes,inodes_free,read_only,hostname\n\
+            2024-01-01 01:00:00,/mnt/sda0,sda0,ext4,SSD,0,1000,0,1000,900,0,alpha\n\
+            2024-01-01 07:00:00,\"/mnt/odd, \"\"name\"\"\",\"odd, \"\"name\"\"\",ext4,SSD,0,1,0,1000,900,0,alpha\n");
+    }
 
-                // If the hostname doesn't exist, write the sys_record to the database
-                if !hostname_exists {
-                    match sys_record.write_to_db(conn.clone()) {
-                        Ok(_) => println!("System data written successfully."),
-                        Err(e) => println!("Error occurred while writing system data: {}", e),
-                    }
+    #[test]
+    fn queries_filter_by_host() {
+        let conn = test_conn();
+        seed_host(conn.clone(), "alpha");
+        seed_host(conn.clone(), "beta");
+        assert_eq!(query_db_all::<RAMRecord>(conn.clone(), None).unwrap().len(), 8);
+        let beta = query_db_all::<DiskRecord>(conn.clone(), Some("beta")).unwrap();
+        assert_eq!(beta.len(), 4);
+        assert!(beta.iter().all(|r| r.host.as_deref() == Some("beta")));
+        let alpha = query_by_dt::<ComponentRecord>(conn.clone(), ts("2024-01-01 00:00:00"), ts("2024-01-01 12:00:00"), Some("alpha")).unwrap();
+        assert_eq!(alpha.len(), 2);
+        assert!(alpha.iter().all(|r| r.host.as_deref() == Some("alpha")));
+        assert!(query_db_all::<RAMRecord>(conn.clone(), Some("gamma")).unwrap().is_empty());
+
+        // both hosts in order of time, and samples without a host only when not filtering
+        conn.writer().lock().unwrap().execute("INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap) VALUES (?1, 1, 1, 0, 0)",
+            [ts("2024-01-01 06:00:00")]).unwrap();
+        let every = range::<RAMRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-01-01 12:00:00").unwrap();
+        let hosts: Vec<Option<&str>> = every.iter().map(|r| r.host.as_deref()).collect();
+        assert_eq!(hosts, [Some("alpha"), Some("beta"), None, Some("alpha"), Some("beta")]);
+        assert_eq!(query_by_dt::<RAMRecord>(conn, ts("2024-01-01 00:00:00"), ts("2024-01-01 12:00:00"), Some("alpha")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn range_queries_search_the_indexes() {
+        assert_eq!(check(test_conn(), false), Ok(()));
+    }
+
+    #[test]
+    fn hosts_list_sample_time_span() {
+        let conn = test_conn();
+        seed_host(conn.clone(), "beta");
+        let alpha = seed_host(conn.clone(), "alpha");
+        sys_record("alpha", "6.1", "2024-01-01 00:00:00").write_to_db(conn.clone(), alpha).unwrap();
+        let hosts = query_hosts(conn, None).unwrap();
+        let names: Vec<&str> = hosts.iter().map(|h| h.hostname.as_str()).collect();
+        assert_eq!(names, vec!["alpha", "beta"]);
+        assert_eq!(hosts[0].os.as_deref(), Some("Linux"));
+        assert_eq!(hosts[1].os, None);
+        assert_eq!(hosts[0].first_sample, Some(ts("2024-01-01 00:00:00")));
+        assert_eq!(hosts[0].last_sample, Some(ts("2024-01-03 00:00:00")));
+    }
+
+    #[test]
+    fn hosts_span_covers_every_sample_table() {
+        let conn = test_conn();
+        let gamma = host_id(&conn.writer().lock().unwrap(), "gamma").unwrap();
+        for dt in ["2024-02-01 00:00:00", "2024-02-05 00:00:00"] {
+            PressureRecord { datetime: ts(dt), host: None, resource: "cpu".to_string(), kind: "some".to_string(),
+                avg10: 1.0, avg60: 1.0, avg300: 1.0, total: 100, stalled: None }
+                .write_to_db(conn.clone(), gamma).unwrap();
+        }
+        let hosts = query_hosts(conn, Some("gamma")).unwrap();
+        assert_eq!((hosts[0].first_sample, hosts[0].last_sample), (Some(ts("2024-02-01 00:00:00")), Some(ts("2024-02-05 00:00:00"))));
+    }
+
+    fn sys_record(hostname: &str, kernel: &str, seen: &str) -> SysRecord {
+        SysRecord {
+            os: "Linux".to_string(),
+            osversion: "12".to_string(),
+            hostname: hostname.to_string(),
+            kernel_version: Some(kernel.to_string()),
+            arch: Some("x86_64".to_string()),
+            cpu_brand: Some("Test CPU".to_string()),
+            cpu_cores: Some(4),
+            boot_time: Some(ts("2024-01-01 00:00:00")),
+            first_seen: Some(ts(seen)),
+            last_seen: Some(ts(seen)),
+        }
+    }
+
+    #[test]
+    fn changed_configuration_adds_a_row() {
+        let shared = test_conn();
+        let conn = shared.writer().lock().unwrap();
+        let host_id = host_id(&conn, "alpha").unwrap();
+        assert!(matches!(record_configuration(&conn, &sys_record("alpha", "6.1", "2024-01-01 00:00:00"), host_id).unwrap(), Configuration::First));
+        let mut rebooted = sys_record("alpha", "6.1", "2024-01-02 00:00:00");
+        rebooted.boot_time = Some(ts("2024-01-02 00:00:00"));
+        assert!(matches!(record_configuration(&conn, &rebooted, host_id).unwrap(), Configuration::Unchanged));
+        match record_configuration(&conn, &sys_record("alpha", "6.5", "2024-01-03 00:00:00"), host_id).unwrap() {
+            Configuration::Changed(changes) => assert_eq!(changes, vec!["Kernel 6.1 -> 6.5"]),
+            _ => panic!("expected a changed configuration"),
+        }
+        drop(conn);
+
+        let records = query_db_all::<SysRecord>(shared, None).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].first_seen, Some(ts("2024-01-01 00:00:00")));
+        assert_eq!(records[0].last_seen, Some(ts("2024-01-02 00:00:00")));
+        assert_eq!(records[0].boot_time, Some(ts("2024-01-02 00:00:00")));
+        assert_eq!(records[1].kernel_version.as_deref(), Some("6.5"));
+    }
+
+    #[test]
+    fn samples_move_last_seen_of_the_current_configuration() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        sys_record("alpha", "6.1", "2024-01-01 00:00:00").write_to_db(conn.clone(), host_id).unwrap();
+        sys_record("alpha", "6.5", "2024-01-02 00:00:00").write_to_db(conn.clone(), host_id).unwrap();
+        write_sample(&mut conn.writer().lock().unwrap(), &synthetic_sample("2024-01-03 00:00:00", 1, 1), host_id).unwrap();
+        let records = query_db_all::<SysRecord>(conn, Some("alpha")).unwrap();
+        assert_eq!(records[0].last_seen, Some(ts("2024-01-01 00:00:00")));
+        assert_eq!(records[1].last_seen, Some(ts("2024-01-03 00:00:00")));
+    }
+
+    #[test]
+    fn timeline_lists_changes_per_host() {
+        let mut upgraded = sys_record("alpha", "6.5", "2024-02-01 00:00:00");
+        upgraded.cpu_cores = Some(8);
+        let legacy = SysRecord { kernel_version: None, arch: None, cpu_brand: None, cpu_cores: None, boot_time: None,
+            first_seen: None, last_seen: None, ..sys_record("beta", "", "2024-01-01 00:00:00") };
+        let timeline = configuration_timeline(&[sys_record("alpha", "6.1", "2024-01-01 00:00:00"), upgraded, legacy]);
+        assert_eq!(timeline.len(), 3);
+        assert!(timeline[0].starts_with("OS: Linux , Version: 12 , Hostname: alpha , Kernel: 6.1"));
+        assert_eq!(timeline[1], "Since: 2024-02-01 00:00:00 , Hostname: alpha , Changed: Kernel 6.1 -> 6.5 , Cores 4 -> 8");
+        assert!(timeline[2].contains("Hostname: beta , Kernel: unknown"));
+    }
+
+    fn synthetic_sample(dt: &str, disks: usize, sensors: usize) -> Sample {
+        Sample {
+            datetime: ts(dt),
+            ram: Some(RAMRecord { datetime: ts(dt), host: None, total_memory: 8000, used_memory: 4000, total_swap: 2000, used_swap: 0 }),
+            disks: (0..disks).map(|i| disk(dt, &format!("sd{}", i), 1000, 500)).collect(),
+            disk_io: Vec::new(),
+            components: (0..sensors).map(|i| component(dt, &format!("sensor{}", i), 40.0)).collect(),
+            cpus: ["total", "cpu0"].iter().map(|cpu| CpuRecord { datetime: ts(dt), host: None, cpu: cpu.to_string(), usage: 12.5, frequency: 2400 }).collect(),
+            load: None,
+            pressure: Vec::new(),
+            cgroups: Vec::new(),
+            networks: Vec::new(),
+            processes: Vec::new(),
+            process_events: Vec::new(),
+            session: None,
+            session_events: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn sample_is_written_in_one_transaction() {
+        let conn = test_conn();
+        let stats = write_sample(&mut conn.writer().lock().unwrap(), &synthetic_sample("2024-01-01 00:00:00", 3, 5), 1).unwrap();
+        assert_eq!((stats.written, stats.failed), (11, 0));
+        assert_eq!(query_db_all::<DiskRecord>(conn.clone(), None).unwrap().len(), 3);
+        assert_eq!(query_db_all::<ComponentRecord>(conn.clone(), None).unwrap().len(), 5);
+        assert_eq!(query_db_all::<CpuRecord>(conn, None).unwrap().len(), 2);
+    }
+
+    // Readers keep listing a whole table while samples are written. Neither waits for the other: every
+    // write goes through while a query is part way through its rows, and every query sees whole samples.
+    #[test]
+    fn heavy_queries_do_not_hold_up_recording() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_stress_{}.db", process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        let db = Arc::new(Database::open(&path).unwrap());
+        let (seeded, written, sensors) = (100, 50, 32);
+        let host_id = host_id(&db.writer().lock().unwrap(), "alpha").unwrap();
+        for _ in 0..seeded {
+            write_sample(&mut db.writer().lock().unwrap(), &synthetic_sample("2024-01-01 00:00:00", 4, sensors), host_id).unwrap();
+        }
+
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let readers: Vec<_> = (0..3).map(|_| {
+            let (db, stop) = (db.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) || seen.is_empty() {
+                    seen.push(query_db_all::<ComponentRecord>(db.clone(), None).unwrap().len());
                 }
+                seen
+            })
+        }).collect();
+
+        // a query stopped part way through its rows holds its snapshot for every write that follows
+        {
+            let reader = db.reader().unwrap();
+            let mut stmt = reader.prepare("SELECT id FROM component").unwrap();
+            let mut rows = stmt.query([]).unwrap();
+            rows.next().unwrap().unwrap();
+            let sample = synthetic_sample("2024-01-02 00:00:00", 4, sensors);
+            for _ in 0..written {
+                let stats = write_sample(&mut db.writer().lock().unwrap(), &sample, host_id).unwrap();
+                assert_eq!(stats.failed, 0);
+            }
+            let mut read = 1;
+            while rows.next().unwrap().is_some() {
+                read += 1;
             }
+            assert_eq!(read, seeded * sensors);
         }
-        Err(e) => {
-            println!("Error retrieving old records: {}", e);
+
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for reader in readers {
+            let seen = reader.join().unwrap();
+            assert!(seen.iter().all(|count| count % sensors == 0), "a query saw part of a sample");
+            assert!(seen.windows(2).all(|pair| pair[0] <= pair[1]));
         }
+        assert_eq!(query_db_all::<RAMRecord>(db.clone(), None).unwrap().len(), seeded + written);
+        Arc::try_unwrap(db).ok().unwrap().close().unwrap();
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn long_ranges_read_the_rollups() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        for (dt, used, temp) in [("2024-01-01 10:00:00", 1000, 40.0), ("2024-01-01 10:00:30", 3000, 98.0), ("2024-01-01 11:00:00", 7000, 50.0)] {
+            let sample = Sample {
+                ram: Some(RAMRecord { datetime: ts(dt), host: None, total_memory: 8000, used_memory: used, total_swap: 2000, used_swap: 0 }),
+                components: vec![component(dt, "cpu", temp)],
+                ..synthetic_sample(dt, 1, 0)
+            };
+            write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        }
+        // a day is read raw, two are read per minute, a month per hour
+        assert_eq!(range::<RAMRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-01-02 00:00:00").unwrap().len(), 3);
+        let minutes = range::<RAMRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-01-03 00:00:00").unwrap();
+        let minutes: Vec<(Timestamp, u64)> = minutes.iter().map(|r| (r.datetime, r.used_memory)).collect();
+        assert_eq!(minutes, vec![(ts("2024-01-01 10:00:00"), 2000), (ts("2024-01-01 11:00:00"), 7000)]);
+        let hours = range::<ComponentRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-02-01 00:00:00").unwrap();
+        let hours: Vec<(Timestamp, f32, bool)> = hours.iter().map(|r| (r.datetime, r.temp, r.near_critical)).collect();
+        assert_eq!(hours, vec![(ts("2024-01-01 10:00:00"), 69.0, true), (ts("2024-01-01 11:00:00"), 50.0, false)]);
+        let disks = range::<DiskRecord>(conn.clone(), "2024-01-01 00:00:00", "2025-06-01 00:00:00").unwrap();
+        assert_eq!(disks.iter().map(|r| (r.mount_point.as_deref(), r.available, r.inodes)).collect::<Vec<_>>(),
+            vec![(Some("/mnt/sd0"), 500, Some(1000))]);
+        // exports stay raw however long the range
+        let mut out = Vec::new();
+        export_csv::<DiskRecord>(conn.clone(), Some((ts("2024-01-01 00:00:00"), ts("2025-06-01 00:00:00"))), None, false, &Zone::Utc, &mut out).unwrap();
+        let exported = String::from_utf8(out).unwrap();
+        assert_eq!(exported.lines().count(), 3);
+        assert!(exported.lines().all(|line| line.contains(",ext4,SSD,0,")), "{}", exported);
+
+        let rollups = query_rollups(conn.clone(), Some((ts("2024-01-01 00:00:00"), ts("2024-01-01 12:00:00"))), None).unwrap();
+        let used = rollups.iter().find(|r| r.metric == "used_memory").unwrap();
+        assert_eq!((used.resolution.as_str(), used.min, used.max, used.avg, used.last, used.count), ("1m", 1000.0, 3000.0, 2000.0, 3000.0, 2));
     }
-}
 
+    fn load_sample(dt: &str, boot_time: &str) -> Sample {
+        Sample {
+            load: Some(LoadRecord { datetime: ts(dt), host: None, one: 0.5, five: 0.25, fifteen: 0.1, uptime: 60, boot_time: ts(boot_time) }),
+            ..synthetic_sample(dt, 0, 0)
+        }
+    }
 
-fn query_db_all<T>(conn: Arc<Mutex<Connection>>) -> Result<Vec<T>>
-where
-    T: Record {
-    let conn = conn.lock().unwrap();
-    let mut stmt = conn.prepare(T::query())?;
-    let record_iter = stmt.query_map([], |row| T::from_row(row))?;
+    #[test]
+    fn reboots_between_samples_become_events() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        for (dt, boot_time) in [
+            ("2024-01-01 01:00:00", "2024-01-01 00:00:00"),
+            ("2024-01-01 01:10:00", "2024-01-01 00:00:01"),
+            ("2024-01-01 02:05:00", "2024-01-01 02:00:00"),
+        ] {
+            write_sample(&mut conn.writer().lock().unwrap(), &load_sample(dt, boot_time), host_id).unwrap();
+        }
+        assert_eq!(query_db_all::<LoadRecord>(conn.clone(), None).unwrap().len(), 3);
+        let events = query_db_all::<EventRecord>(conn.clone(), Some("alpha")).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].datetime, events[0].kind.as_str()), (ts("2024-01-01 02:00:00"), "reboot"));
+        assert_eq!(events[0].detail, "Previous Boot: 2024-01-01 00:00:01 , Last Sample Before: 2024-01-01 01:10:00");
+        // events show up in range queries around the reboot
+        assert_eq!(range::<EventRecord>(conn, "2024-01-01 01:30:00", "2024-01-01 02:30:00").unwrap().len(), 1);
+    }
 
-    let mut records = Vec::new();
-    for record in record_iter {
-        records.push(record?);
+    #[test]
+    fn counter_rates_handle_resets() {
+        assert_eq!(counter_rate(1000, 3000, 2.0), Some(1000.0));
+        // the interface was recreated, whether its counters were small or large
+        assert_eq!(counter_rate(50, 5, 1.0), None);
+        assert_eq!(counter_rate(u32::MAX as u64 - 99, 100, 1.0), None);
+        assert_eq!(counter_rate(u32::MAX as u64 + 1000, 10, 1.0), None);
+        assert_eq!(counter_rate(0, 10, 0.0), None);
     }
 
-    Ok(records)
-}
+    fn counters(received: u64) -> NetworkCounters {
+        NetworkCounters { received, transmitted: received / 2, ..Default::default() }
+    }
 
+    #[test]
+    fn network_rates_skip_new_and_returning_interfaces() {
+        let first = HashMap::from([("eth0".to_string(), counters(1000)), ("wlan0".to_string(), counters(50))]);
+        let (records, events) = network_sample(ts("2024-01-01 00:00:00"), &first, None, 0.0);
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|r| r.rates.received.is_none()));
+        assert!(events.is_empty());
+
+        // wlan0 went away and a vpn came up
+        let second = HashMap::from([("eth0".to_string(), counters(3000)), ("tun0".to_string(), counters(10))]);
+        let (records, events) = network_sample(ts("2024-01-01 00:00:10"), &second, Some(&first), 10.0);
+        let interfaces: Vec<&str> = records.iter().map(|r| r.interface.as_str()).collect();
+        assert_eq!(interfaces, vec!["eth0", "tun0"]);
+        assert_eq!((records[0].rates.received, records[0].rates.transmitted), (Some(200.0), Some(100.0)));
+        assert_eq!(records[1].rates.received, None);
+        let details: Vec<&str> = events.iter().map(|e| e.detail.as_str()).collect();
+        assert_eq!(details, vec!["Interface tun0 added", "Interface wlan0 removed"]);
+
+        // wlan0 comes back, it is not compared with its counters from before it disappeared
+        let third = HashMap::from([("wlan0".to_string(), counters(60))]);
+        let (records, _) = network_sample(ts("2024-01-01 00:00:20"), &third, Some(&second), 10.0);
+        assert_eq!(records[0].rates.received, None);
+    }
 
-fn print_records<T>(records: Result<Vec<T>>) -> Result<()>
-where
-    T: Record + std::fmt::Display {
-    match records {
-        Ok(records) => {
-            for record in records {
-                println!("{}", record);
+    #[test]
+    fn network_records_round_trip() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let first = HashMap::from([("eth0".to_string(), counters(1000))]);
+        let second = HashMap::from([("eth0".to_string(), counters(1500))]);
+        let (records, _) = network_sample(ts("2024-01-01 00:00:10"), &second, Some(&first), 10.0);
+        let sample = Sample { networks: records, ..synthetic_sample("2024-01-01 00:00:10", 0, 0) };
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        let records = range::<NetworkRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].counters.received, records[0].rates.received, records[0].rates.errors_received), (1500, Some(50.0), Some(0.0)));
+    }
+
+    #[test]
+    fn temperatures_near_critical_are_flagged_and_queried() {
+        assert!(near_critical(96.0, Some(100.0), 5.0));
+        assert!(near_critical(95.0, Some(100.0), 5.0));
+        assert!(!near_critical(94.9, Some(100.0), 5.0));
+        assert!(!near_critical(150.0, None, 5.0));
+
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let sample = Sample {
+            components: vec![component("2024-01-01 00:00:00", "cpu", 42.5), component("2024-01-01 00:00:00", "gpu", 97.25)],
+            ..synthetic_sample("2024-01-01 00:00:00", 0, 0)
+        };
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        let all = range::<ComponentRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        // stored as REAL, no longer truncated
+        assert_eq!(all[0].temp, 42.5);
+        let hot = query_near_critical(conn.clone(), None, Some("alpha")).unwrap();
+        assert_eq!(hot.len(), 1);
+        assert_eq!((hot[0].label.as_str(), hot[0].temp, hot[0].critical), ("gpu", 97.25, Some(100.0)));
+        assert!(query_near_critical(conn.clone(), Some((ts("2024-01-02 00:00:00"), ts("2024-01-03 00:00:00"))), None).unwrap().is_empty());
+        assert!(query_kind(conn, RecordKind::Disk, None, None, None, None, true).is_err());
+    }
+
+    // /proc/pressure/cpu and io of a 5.15 kernel. cpu only gained its "full" line in 5.13
+    const PRESSURE_CPU: &str = "some avg10=1.52 avg60=0.87 avg300=0.30 total=41252617\nfull avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+    const PRESSURE_IO: &str = "some avg10=12.00 avg60=4.10 avg300=1.00 total=987654321\nfull avg10=10.50 avg60=3.00 avg300=0.75 total=876543210\n";
+
+    // A directory of files for a test, removed when dropped so a failing assertion doesn't leave it behind
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+
+        fn new(name: &str, files: &[(&str, &str)]) -> TestDir {
+            let root = std::env::temp_dir().join(format!("sysinfo_db_test_{}_{}", process::id(), name));
+            fs::create_dir_all(&root).unwrap();
+            for (file, contents) in files {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
             }
-            Ok(())
+            TestDir(root)
         }
-        Err(e) => Err(e),
     }
-}
 
-fn query_by_dt<T>(conn: Arc<Mutex<Connection>>, start_dt: String, end_dt: String) -> Result<Vec<T>>
-where 
-    T: Record {
+    impl std::ops::Deref for TestDir {
+        type Target = Path;
 
-    let conn = conn.lock().unwrap();
-    let mut stmt = conn.prepare(&T::query_by_dt(start_dt, end_dt))?;
-    let record_iter = match stmt.query_map([], |row| T::from_row(row)) {
-        Ok(record_iter) => record_iter,
-        Err(e) => {
-            eprintln!("Failed to execute query: {}", e);
-            return Err(e);
+        fn deref(&self) -> &Path {
+            &self.0
         }
-    };
-    
-    let mut records = Vec::new();
-    for record in record_iter {
-        records.push(record?);
     }
 
-    Ok(records)
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
 
+    #[test]
+    fn pressure_files_are_parsed() {
+        let records = parse_pressure(ts("2024-01-01 00:00:00"), "io", PRESSURE_IO);
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].kind.as_str(), records[0].avg10, records[0].avg300, records[0].total), ("some", 12.0, 1.0, 987654321));
+        assert_eq!((records[1].kind.as_str(), records[1].avg60), ("full", 3.0));
+        assert!(parse_pressure(ts("2024-01-01 00:00:00"), "io", "some avg10=oops\n\n").is_empty());
+    }
 
-}
+    #[test]
+    fn missing_pressure_files_give_no_records() {
+        // memory is missing, as is the whole directory on kernels without PSI
+        let root = TestDir::new("pressure", &[("cpu", PRESSURE_CPU), ("io", PRESSURE_IO)]);
+        let records = read_pressure(&root, ts("2024-01-01 00:00:00"));
+        let resources: Vec<(&str, &str)> = records.iter().map(|r| (r.resource.as_str(), r.kind.as_str())).collect();
+        assert_eq!(resources, vec![("cpu", "some"), ("cpu", "full"), ("io", "some"), ("io", "full")]);
+        let path = root.to_path_buf();
+        drop(root);
+        assert!(read_pressure(&path, ts("2024-01-01 00:00:00")).is_empty());
+    }
 
-fn query_choice<T>(conn: Arc<Mutex<Connection>>) 
-where 
-    T: Record {
-    loop {
-        println!("Choose how to query Records:");
-        println!("1.    All Records");
-        println!("2.    By Date Time");
-        println!("3.    Go Back");
-        let input = read_string("");
-        let input: u8 = match input.trim().parse() {
-            Ok(i) => i,
-            Err(_) => {
-                println!("Invlaid Input. Please enter a number.");
-                continue;
-            }
+    #[test]
+    fn pressure_records_round_trip() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let mut records = parse_pressure(ts("2024-01-01 00:00:00"), "cpu", PRESSURE_CPU);
+        records[0].stalled = Some(2500);
+        let sample = Sample { pressure: records, ..synthetic_sample("2024-01-01 00:00:00", 0, 0) };
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        let records = range::<PressureRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].avg10, records[0].total, records[0].stalled), (1.52, 41252617, Some(2500)));
+        assert_eq!((records[1].kind.as_str(), records[1].stalled, records[1].host.as_deref()), ("full", None, Some("alpha")));
+    }
+
+    // a cgroup v2 root with a slice, a container with every controller and an idle service without io
+    fn cgroup_root(name: &str) -> TestDir {
+        TestDir::new(name, &[
+            ("cgroup.controllers", "cpuset cpu io memory pids\n"),
+            ("memory.current", "999999\n"),
+            ("system.slice/memory.current", "5000\n"),
+            ("system.slice/docker-4f2a.scope/memory.current", "3000\n"),
+            ("system.slice/docker-4f2a.scope/memory.max", "8192\n"),
+            ("system.slice/docker-4f2a.scope/cpu.stat", "usage_usec 1500\nuser_usec 1000\nsystem_usec 500\nnr_periods 0\n"),
+            ("system.slice/docker-4f2a.scope/io.stat", "8:0 rbytes=4096 wbytes=8192 rios=1 wios=2 dbytes=0 dios=0\n259:0 rbytes=4096 wbytes=0 rios=1 wios=0 dbytes=0 dios=0\n"),
+            ("system.slice/cron.service/memory.current", "2000\n"),
+            ("system.slice/cron.service/memory.max", "max\n"),
+        ])
+    }
+
+    fn cgroups(include: &[&str], exclude: &[&str]) -> Cgroups {
+        let file = format!("[cgroups]\ninclude = {:?}\nexclude = {:?}", include, exclude);
+        Config::from_sources(Some(&file), |_| None, &config::Overrides::default()).unwrap().cgroups
+    }
+
+    #[test]
+    fn cgroups_are_read_below_the_root() {
+        let root = cgroup_root("cgroups");
+        let records = read_cgroups(&root, ts("2024-01-01 00:00:00"), &cgroups(&["**"], &[]));
+        let paths: Vec<&str> = records.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["system.slice", "system.slice/cron.service", "system.slice/docker-4f2a.scope"]);
+        let docker = &records[2];
+        assert_eq!((docker.memory_current, docker.memory_max), (Some(3000), Some(8192)));
+        assert_eq!((docker.cpu_usage, docker.cpu_user, docker.cpu_system), (Some(1500), Some(1000), Some(500)));
+        assert_eq!((docker.io_read_bytes, docker.io_write_bytes, docker.io_reads, docker.io_writes), (Some(8192), Some(8192), Some(2), Some(2)));
+        // unlimited, and no cpu or io controller
+        let cron = &records[1];
+        assert_eq!((cron.memory_current, cron.memory_max, cron.cpu_usage, cron.io_reads), (Some(2000), None, None, None));
+
+        let records = read_cgroups(&root, ts("2024-01-01 00:00:00"), &cgroups(&["system.slice/*"], &["*/cron.*"]));
+        let paths: Vec<&str> = records.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["system.slice/docker-4f2a.scope"]);
+
+        // without cgroup.controllers the hierarchy is not cgroup v2
+        fs::remove_file(root.join("cgroup.controllers")).unwrap();
+        assert!(read_cgroups(&root, ts("2024-01-01 00:00:00"), &cgroups(&["**"], &[])).is_empty());
+    }
+
+    #[test]
+    fn cgroup_records_round_trip() {
+        let root = cgroup_root("cgroup_round_trip");
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let sample = Sample {
+            cgroups: read_cgroups(&root, ts("2024-01-01 00:00:00"), &cgroups(&["**"], &[])),
+            ..synthetic_sample("2024-01-01 00:00:00", 0, 0)
         };
-        match input {
-            1 => {
-                let _ = print_records(query_db_all::<T>(conn.clone()));
-            },
-            2 => {
-                let dates = get_datetime_range();
-                if dates[0] == "" {
-                    break;
-                }
-                let start_dt = &dates[0];
-                let end_dt = &dates[1];
-                let _ = print_records::<T>(query_by_dt::<T>(conn.clone(), start_dt.to_string(), end_dt.to_string()));
+        drop(root);
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        let records = range::<CgroupRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        assert_eq!(records.len(), 3);
+        let docker = records.iter().find(|r| r.path == "system.slice/docker-4f2a.scope").unwrap();
+        assert_eq!((docker.memory_max, docker.io_write_bytes, docker.host.as_deref()), (Some(8192), Some(8192), Some("alpha")));
+    }
 
-            },
-            3 => {
-                break;
-            }
-            _ => {
-                println!("Please enter one of the options given.");
-                continue;
+    #[test]
+    fn mounts_are_compared_between_samples() {
+        let first = HashMap::from([("/".to_string(), Some(false)), ("/mnt/usb".to_string(), Some(false)), ("/mnt/nfs".to_string(), None)]);
+        assert!(mount_events(ts("2024-01-01 00:00:00"), &first, None).is_empty());
+
+        // the usb stick was pulled, / went read-only after an error and a backup disk was plugged in
+        let second = HashMap::from([("/".to_string(), Some(true)), ("/mnt/backup".to_string(), Some(false)), ("/mnt/nfs".to_string(), Some(true))]);
+        let events = mount_events(ts("2024-01-01 00:00:10"), &second, Some(&first));
+        let details: Vec<&str> = events.iter().map(|e| e.detail.as_str()).collect();
+        assert_eq!(details, vec!["Remounted / read-only", "Mounted /mnt/backup", "Unmounted /mnt/usb"]);
+        assert!(events.iter().all(|e| e.kind == "mount"));
+
+        let third = HashMap::from([("/".to_string(), Some(false)), ("/mnt/backup".to_string(), Some(false)), ("/mnt/nfs".to_string(), Some(true))]);
+        let details: Vec<String> = mount_events(ts("2024-01-01 00:00:20"), &third, Some(&second)).into_iter().map(|e| e.detail).collect();
+        assert_eq!(details, vec!["Remounted / read-write"]);
+    }
+
+    #[test]
+    fn mount_stats_come_from_statvfs() {
+        let stats = mount_stats(&std::env::temp_dir()).unwrap();
+        assert!(!stats.read_only);
+        assert!(stats.inodes.is_none() || stats.inodes_free <= stats.inodes);
+        assert!(mount_stats(Path::new("/no/such/mount")).is_none());
+    }
+
+    fn utmp_record(kind: libc::c_short, pid: i32, tty: &str, user: &str, remote_host: &str, login_time: i32) -> Vec<u8> {
+        // SAFETY: utmpx is plain integers and arrays, all zeroes is an empty entry
+        let mut entry: libc::utmpx = unsafe { mem::zeroed() };
+        entry.ut_type = kind;
+        entry.ut_pid = pid;
+        let copy = |field: &mut [libc::c_char], value: &str| {
+            for (c, byte) in field.iter_mut().zip(value.bytes()) {
+                *c = byte as libc::c_char;
             }
-        }
+        };
+        copy(&mut entry.ut_line, tty);
+        copy(&mut entry.ut_user, user);
+        copy(&mut entry.ut_host, remote_host);
+        entry.ut_tv.tv_sec = login_time as _;
+        // SAFETY: entry lives until the bytes are copied out of it
+        unsafe { std::slice::from_raw_parts(&entry as *const libc::utmpx as *const u8, mem::size_of::<libc::utmpx>()) }.to_vec()
     }
 
+    fn session(tty: &str, user: &str, pid: u32, login_time: u64) -> Session {
+        Session { user: user.to_string(), tty: tty.to_string(), remote_host: None, pid, login_time }
+    }
 
-}
+    #[test]
+    fn utmp_user_sessions_are_parsed() {
+        let mut utmp = utmp_record(libc::BOOT_TIME, 0, "~", "reboot", "", 1704067200);
+        // a getty waiting on tty1 is a LOGIN_PROCESS
+        utmp.extend(utmp_record(libc::LOGIN_PROCESS, 612, "tty1", "LOGIN", "", 1704067205));
+        utmp.extend(utmp_record(libc::USER_PROCESS, 4242, "pts/0", "alice", "10.0.0.7", 1704070800));
+        utmp.extend(utmp_record(libc::USER_PROCESS, 4300, "tty2", "bob", "", 1704070900));
+        let sessions = parse_utmp(&utmp).unwrap();
+        assert_eq!(sessions, vec![
+            Session { user: "alice".to_string(), tty: "pts/0".to_string(), remote_host: Some("10.0.0.7".to_string()), pid: 4242, login_time: 1704070800 },
+            session("tty2", "bob", 4300, 1704070900),
+        ]);
+        // records of another layout don't add up to whole utmpx entries
+        utmp.extend(&utmp_record(libc::USER_PROCESS, 1, "pts/1", "eve", "", 0)[..100]);
+        assert_eq!(parse_utmp(&utmp), None);
+    }
 
+    #[test]
+    fn sessions_starting_and_ending_become_events() {
+        let uid = |name: &str| (name == "alice").then_some(1000);
+        let first = vec![session("pts/0", "alice", 10, 1704070800), session("tty2", "bob", 20, 1704070900)];
+        assert!(session_events(ts("2030-01-01 00:00:00"), None, &first, uid).is_empty());
+
+        // bob logged out, alice opened a second terminal. Starts are dated by the login, ends by the sample
+        let second = vec![session("pts/0", "alice", 10, 1704070800), session("pts/1", "alice", 30, 1704074000)];
+        let events = session_events(ts("2030-01-01 00:00:10"), Some(&first), &second, uid);
+        let summary: Vec<(&str, &str, &str, Option<u32>)> = events.iter().map(|e| (e.event.as_str(), e.user.as_str(), e.tty.as_str(), e.uid)).collect();
+        assert_eq!(summary, vec![("start", "alice", "pts/1", Some(1000)), ("end", "bob", "tty2", None)]);
+        let login = Timestamp::from_secs(1704074000);
+        assert_eq!((events[0].datetime, events[0].login_time), (login, login));
+        assert_eq!(events[1].datetime, ts("2030-01-01 00:00:10"));
+    }
 
-fn read_string(prompt: &str) -> String {
+    #[test]
+    fn session_records_round_trip() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let dt = "2024-01-01 00:00:10";
+        let mut ended = session("pts/0", "alice", 10, 1704070800);
+        ended.remote_host = Some("laptop".to_string());
+        let sample = Sample {
+            session: Some(SessionRecord { datetime: ts(dt), host: None, active: 3, users: 2 }),
+            session_events: vec![ended.event("end", ts(dt), Some(1000))],
+            ..synthetic_sample(dt, 0, 0)
+        };
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        let counts = range::<SessionRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        assert_eq!((counts[0].active, counts[0].users), (3, 2));
+        let events = range::<SessionEventRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        assert_eq!((events[0].user.as_str(), events[0].uid, events[0].remote_host.as_deref()), ("alice", Some(1000), Some("laptop")));
+    }
 
-    println!("{}", prompt);
-    let mut input: String = String::new();
-    std::io::stdin()
-        .read_line(&mut input)
-        .expect("Failed to read.");
-    input
-}
+    const DISKSTATS: &str = "   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+   8       0 sda 1200 30 96000 800 400 12 8000 900 0 1500 1700 0 0 0 0 10 5
+   8       1 sda1 1100 30 90000 700 390 12 7800 880 0 1400 1580 0 0 0 0 0 0
+ 259       0 nvme0n1 50 0 4000 20 10 0 160 5 0 30 25
+ short line";
+
+    #[test]
+    fn diskstats_are_parsed() {
+        let stats = parse_diskstats(DISKSTATS);
+        assert_eq!(stats.len(), 4);
+        let sda = stats["sda"];
+        assert_eq!((sda.reads, sda.read_sectors, sda.writes, sda.write_sectors), (1200, 96000, 400, 8000));
+        // older kernels print fewer fields
+        assert_eq!(stats["nvme0n1"].write_sectors, 160);
+    }
 
-fn parse_datetime_range(dt_range: String) -> Vec<String> {
-    let re = Regex::new(r"[0-9]{4}-[0-9]{2}-[0-9]{2} [0-9]{2}:[0-9]{2}:[0-9]{2}").unwrap();
+    #[test]
+    fn disk_io_is_the_increase_since_the_previous_sample() {
+        let previous = parse_diskstats(DISKSTATS);
+        let mut current = previous.clone();
+        current.insert("sda".to_string(), DiskStats { reads: 1210, read_sectors: 96016, writes: 420, write_sectors: 8100 });
+        // the device was replaced, its counters start again
+        current.insert("nvme0n1".to_string(), DiskStats { reads: 5, read_sectors: 40, writes: 1, write_sectors: 8 });
+        current.insert("sdb".to_string(), DiskStats { reads: 5, read_sectors: 40, writes: 1, write_sectors: 8 });
+        let records = disk_io_sample(ts("2024-01-01 00:00:10"), &current, &previous, 10.0);
+        // loop0 never did any I/O, sdb has nothing to compare with and nvme0n1 only counts since it was replaced
+        let devices: Vec<&str> = records.iter().map(|r| r.device.as_str()).collect();
+        assert_eq!(devices, vec!["sda", "sda1"]);
+        assert_eq!((records[0].reads, records[0].read_bytes, records[0].writes, records[0].write_bytes), (10, 16 * 512, 20, 100 * 512));
+        assert_eq!(records[1].reads, 0);
+    }
 
-    let dates: Vec<String> = re.find_iter(dt_range.as_str())
-        .map(|m| m.as_str().to_string())
-        .collect();
-    dates
-    
-}
+    #[test]
+    fn disk_records_round_trip() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let previous = parse_diskstats(DISKSTATS);
+        let mut current = previous.clone();
+        current.insert("sda".to_string(), DiskStats { reads: 1300, ..previous["sda"] });
+        let sample = Sample {
+            disk_io: disk_io_sample(ts("2024-01-01 00:00:10"), &current, &previous, 10.0),
+            ..synthetic_sample("2024-01-01 00:00:10", 1, 0)
+        };
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
 
-fn get_datetime_range() -> Vec<String> {
-    println!("press 'q' to quit at any time.");
-    loop {
-        let dt_range = read_string("Enter a date time range (YYYY-MM-DD HH:MM:SS --- YYYY-MM-DD HH:MM:SS):\n");
-        if dt_range.trim() == "q" {
-            let empty = vec!("".to_string());
-            return empty
+        let disks = range::<DiskRecord>(conn.clone(), "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        assert_eq!((disks[0].mount_point.as_deref(), disks[0].removable), (Some("/mnt/sd0"), Some(false)));
+        let io = range::<DiskIoRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 00:01:00").unwrap();
+        let sda = io.iter().find(|r| r.device == "sda").unwrap();
+        assert_eq!((sda.reads, sda.seconds, sda.host.as_deref()), (100, 10.0, Some("alpha")));
+    }
+
+    fn process(dt: &str, pid: u32, name: &str, cpu: f32, memory: u64) -> ProcessRecord {
+        ProcessRecord { datetime: ts(dt), host: None, pid, name: name.to_string(), cmd: format!("/usr/bin/{}", name), user: None,
+            memory, virtual_memory: memory * 2, cpu, start_time: ts("2024-01-01 00:00:00"), rank: String::new() }
+    }
+
+    #[test]
+    fn top_processes_are_kept_once_per_ranking() {
+        let dt = "2024-01-01 00:00:00";
+        let processes = vec![
+            process(dt, 1, "init", 0.0, 10),
+            process(dt, 2, "compiler", 90.0, 500),
+            process(dt, 3, "browser", 20.0, 900),
+            process(dt, 4, "shell", 5.0, 20),
+        ];
+        let options = Processes { top: 2, rank_by: vec![ProcessRank::Cpu, ProcessRank::Memory] };
+        let top = top_processes(processes, &options);
+        let kept: Vec<(u32, &str)> = top.iter().map(|p| (p.pid, p.rank.as_str())).collect();
+        assert_eq!(kept, vec![(2, "cpu,memory"), (3, "cpu,memory")]);
+
+        let processes = vec![process(dt, 1, "init", 0.0, 10), process(dt, 2, "compiler", 90.0, 5), process(dt, 3, "browser", 20.0, 900)];
+        let options = Processes { top: 1, rank_by: vec![ProcessRank::Cpu, ProcessRank::VirtualMemory] };
+        let kept: Vec<(u32, String)> = top_processes(processes, &options).into_iter().map(|p| (p.pid, p.rank)).collect();
+        assert_eq!(kept, vec![(2, "cpu".to_string()), (3, "virtual_memory".to_string())]);
+    }
+
+    #[test]
+    fn processes_are_queried_by_name_and_range() {
+        let conn = test_conn();
+        let alpha = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let beta = host_id(&conn.writer().lock().unwrap(), "beta").unwrap();
+        for (dt, host_id) in [("2024-01-01 00:00:00", alpha), ("2024-01-01 00:01:00", alpha), ("2024-01-01 00:02:00", alpha), ("2024-01-01 00:01:00", beta)] {
+            let sample = Sample {
+                processes: vec![process(dt, 2, "compiler", 90.0, 500), process(dt, 3, "browser", 20.0, 900)],
+                ..synthetic_sample(dt, 0, 0)
+            };
+            write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
         }
-        let dates = parse_datetime_range(dt_range);
-        match dates.len() {
-            0 => {
-                println!("No datetime range given, please follow the format provided.");
-                continue;
-            }
-            1 => {
-                println!("Only 1 datetime given, please give two datetimes to form a range you want to query.");
-                continue;
+        assert_eq!(query_processes(conn.clone(), "compiler", None, None).unwrap().len(), 4);
+        let records = query_processes(conn.clone(), "compiler", Some((ts("2024-01-01 00:01:00"), ts("2024-01-01 00:02:00"))), Some("alpha")).unwrap();
+        let times: Vec<Timestamp> = records.iter().map(|r| r.datetime).collect();
+        assert_eq!(times, vec![ts("2024-01-01 00:01:00"), ts("2024-01-01 00:02:00")]);
+        assert!(records.iter().all(|r| r.name == "compiler" && r.host.as_deref() == Some("alpha")));
+        assert!(query_kind(conn.clone(), RecordKind::Process, Some("yesterday".to_string()), Some("today".to_string()), None, Some("compiler".to_string()), false).is_err());
+        assert!(query_kind(conn, RecordKind::Ram, None, None, None, Some("compiler".to_string()), false).is_err());
+    }
+
+    fn tracked(pid: u32, start_time: u64, memory: u64, cpu: f32) -> TrackedProcess {
+        TrackedProcess { pid, ppid: Some(1), name: format!("proc{}", pid), cmd: format!("proc{} --flag", pid), start_time, peak_memory: memory, peak_cpu: cpu }
+    }
+
+    #[test]
+    fn process_spawns_and_exits_are_tracked_with_peaks() {
+        // 2024-01-01 00:00:00 UTC
+        let started = 1704067200;
+        let (t0, t1, t2) = (ts("2024-01-01 00:01:00"), ts("2024-01-01 00:01:10"), ts("2024-01-01 00:01:20"));
+        let (running, events) = track_processes(t0, None, vec![tracked(10, started, 100, 1.0), tracked(11, started, 50, 0.0)]);
+        assert!(events.is_empty());
+
+        let (running, events) = track_processes(t1, Some(&running), vec![tracked(10, started, 300, 0.5), tracked(12, started + 5, 20, 2.0)]);
+        let seen: Vec<(&str, u32)> = events.iter().map(|e| (e.event.as_str(), e.pid)).collect();
+        assert_eq!(seen, vec![("spawn", 12), ("exit", 11)]);
+        assert_eq!(events[0].datetime, Timestamp::from_secs(started + 5));
+        assert_eq!(events[1].datetime, t1);
+
+        // pid 10 was reused by a new process, the old one exited with the peaks it reached
+        let (_, events) = track_processes(t2, Some(&running), vec![tracked(10, started + 60, 10, 0.0), tracked(12, started + 5, 20, 2.0)]);
+        let exit = events.iter().find(|e| e.event == "exit").unwrap();
+        assert_eq!((exit.pid, exit.peak_memory, exit.peak_cpu), (10, 300, 1.0));
+        assert!(events.iter().any(|e| e.event == "spawn" && e.pid == 10));
+    }
+
+    #[test]
+    fn process_events_are_a_timeline() {
+        let conn = test_conn();
+        let host_id = host_id(&conn.writer().lock().unwrap(), "alpha").unwrap();
+        let event = |datetime: &str, event: &str, pid: u32| ProcessEventRecord { datetime: ts(datetime), host: None, event: event.to_string(),
+            pid, ppid: None, name: "job".to_string(), cmd: "job".to_string(), start_time: ts("2024-01-01 00:00:00"), peak_memory: 1, peak_cpu: 0.0 };
+        let sample = Sample {
+            process_events: vec![event("2024-01-01 00:05:00", "exit", 1), event("2024-01-01 00:01:00", "spawn", 2)],
+            ..synthetic_sample("2024-01-01 00:05:00", 0, 0)
+        };
+        write_sample(&mut conn.writer().lock().unwrap(), &sample, host_id).unwrap();
+        let timeline = range::<ProcessEventRecord>(conn, "2024-01-01 00:00:00", "2024-01-01 01:00:00").unwrap();
+        let seen: Vec<(&str, u32)> = timeline.iter().map(|e| (e.event.as_str(), e.pid)).collect();
+        assert_eq!(seen, vec![("spawn", 2), ("exit", 1)]);
+    }
+
+    #[test]
+    fn failed_records_are_counted_and_the_rest_kept() {
+        let conn = test_conn();
+        conn.writer().lock().unwrap().execute_batch("DROP TABLE component").unwrap();
+        let stats = write_sample(&mut conn.writer().lock().unwrap(), &synthetic_sample("2024-01-01 00:00:00", 2, 3), 1).unwrap();
+        // the rollup of the missing table fails as well
+        assert_eq!((stats.written, stats.failed, stats.errors.len()), (5, 3, 4));
+        assert_eq!(query_db_all::<DiskRecord>(conn, None).unwrap().len(), 2);
+    }
+
+    // Range queries on a synthetic database of two hosts sampled every 10 seconds for 60 days (a million
+    // ram and four million cpu rows), with the indexes and after dropping them.
+    // cargo test --release -- --ignored --nocapture bench_range_queries
+    #[test]
+    #[ignore]
+    fn bench_range_queries() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_bench_range_{}.db", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut conn = Connection::open(&path).unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        let samples = 60 * 8640;
+        let first = ts("2024-01-01 00:00:00");
+        conn.execute_batch("INSERT INTO host (hostname) VALUES ('alpha'), ('beta')").unwrap();
+        let start = std::time::Instant::now();
+        conn.execute("WITH RECURSIVE tick(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM tick WHERE i + 1 < ?1)
+            INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap, host_id)
+            SELECT ?2 + i * 10000, 8000, i % 8000, 2000, 0, h.id FROM tick, host h", (samples, first)).unwrap();
+        conn.execute("WITH RECURSIVE tick(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM tick WHERE i + 1 < ?1)
+            INSERT INTO cpu (datetime, cpu, usage, frequency, host_id)
+            SELECT ?2 + i * 10000, c.cpu, i % 100, 2400, h.id FROM tick, host h, (SELECT 'total' AS cpu UNION ALL SELECT 'cpu0' UNION ALL SELECT 'cpu1' UNION ALL SELECT 'cpu2') c",
+            (samples, first)).unwrap();
+        println!("generated in {:?}", start.elapsed());
+        drop(conn);
+        let conn = Arc::new(Database::open(&path).unwrap());
+
+        let middle = ts("2024-01-30 12:00:00");
+        let time = |f: &dyn Fn() -> usize| {
+            let runs = 5;
+            let start = std::time::Instant::now();
+            let mut rows = 0;
+            for _ in 0..runs {
+                rows = f();
             }
-            2 => {
-                return dates
+            (start.elapsed() / runs, rows)
+        };
+        for indexed in [true, false] {
+            if !indexed {
+                conn.writer().lock().unwrap().execute_batch("DROP INDEX ram_host_datetime; DROP INDEX cpu_host_datetime").unwrap();
             }
-            _ => {
-                println!("Too many datetimes provided. Please enter two datetimes to form a range you want to query.")
+            for (span, end) in [("an hour", ts("2024-01-30 13:00:00")), ("a day", ts("2024-01-31 12:00:00"))] {
+                for host in [None, Some("beta")] {
+                    let start = middle;
+                    let (ram, ram_rows) = time(&|| query_by_dt::<RAMRecord>(conn.clone(), start, end, host).unwrap().len());
+                    let (cpu, cpu_rows) = time(&|| query_by_dt::<CpuRecord>(conn.clone(), start, end, host).unwrap().len());
+                    println!("{} over {}, {}: ram {:?} ({} rows), cpu {:?} ({} rows)", if indexed { "indexed" } else { "scanned" },
+                        span, host.unwrap_or("every host"), ram, ram_rows, cpu, cpu_rows);
+                }
             }
         }
-        
+        let _ = std::fs::remove_file(&path);
     }
 
-}
-
-fn write_all_records(sys: &mut SystemData, conn: Arc<Mutex<Connection>>, p: bool) {
-
-    sys.refresh_all();
-            let dt = Local::now();
-            let dt = dt.format("%Y-%m-%d %H:%M:%S").to_string();
-
-            let ram_record = RAMRecord {
-                datetime: dt.clone(),
-                total_memory: sys.total_memory(),
-                used_memory: sys.used_memory(),
-                total_swap: sys.total_swap(),
-                used_swap: sys.used_swap()
-            };
-            let _ = ram_record.write_to_db(conn.clone());
-            if p {
-                println!("{}", ram_record);
-            }
-
-            let disks = Disks::new_with_refreshed_list();
-            for disk in &disks {
-                let disk_record = DiskRecord {
-                    datetime: dt.clone(),
-                    name: disk.name().to_str().unwrap().to_string(),
-                    total: disk.total_space(),
-                    available: disk.available_space()
-                };
-                let _ = disk_record.write_to_db(conn.clone());
-                if p {
-                    println!("{}", disk_record);
-                }
+    // cargo test --release -- --ignored --nocapture bench_write_throughput
+    #[test]
+    #[ignore]
+    fn bench_write_throughput() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_bench_{}.db", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let conn = Arc::new(Database::open(&path).unwrap());
+
+        for (disks, sensors) in [(4, 8), (64, 256)] {
+            let ticks = 100;
+            let sample = synthetic_sample("2024-01-01 00:00:00", disks, sensors);
+            let records = (ticks * sample.len()) as f64;
+
+            let start = std::time::Instant::now();
+            for _ in 0..ticks {
+                write_sample(&mut conn.writer().lock().unwrap(), &sample, 1).unwrap();
             }
-            
-            let components = Components::new_with_refreshed_list();
-            for component in &components {
-                let component_record = ComponentRecord {
-                    datetime: dt.clone(),
-                    label: component.label().to_string(),
-                    temp: component.temperature()
-                };
-                let _ = component_record.write_to_db(conn.clone());
-                if p {
-                    println!("{}", component_record);
+            let batched = start.elapsed();
+
+            // one autocommit per record, as before batching
+            let start = std::time::Instant::now();
+            for _ in 0..ticks {
+                sample.ram.as_ref().unwrap().write_to_db(conn.clone(), 1).unwrap();
+                for disk_record in &sample.disks {
+                    disk_record.write_to_db(conn.clone(), 1).unwrap();
+                }
+                for component_record in &sample.components {
+                    component_record.write_to_db(conn.clone(), 1).unwrap();
+                }
+                for cpu_record in &sample.cpus {
+                    cpu_record.write_to_db(conn.clone(), 1).unwrap();
                 }
             }
+            let unbatched = start.elapsed();
 
-}
\ No newline at end of file
+            println!("{} disks, {} sensors: batched {:.0} records/s ({:?}/tick), per record {:.0} records/s ({:?}/tick)",
+                disks, sensors,
+                records / batched.as_secs_f64(), batched / ticks as u32,
+                records / unbatched.as_secs_f64(), unbatched / ticks as u32);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
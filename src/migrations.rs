@@ -0,0 +1,498 @@
+use rusqlite::Connection;
+
+// Forward migrations, in order. Migration N (1 based) moves a database from schema version N-1 to N,
+// the version is stored in PRAGMA user_version. Never edit a migration once released, add a new one.
+const MIGRATIONS: &[&str] = &[
+    // 1: baseline schema. Databases created before versioning are at version 0 but may already have
+    // these tables, hence IF NOT EXISTS
+    "CREATE TABLE IF NOT EXISTS component (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        label TEXT NOT NULL,
+        temp INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS disk (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        name TEXT NOT NULL,
+        total INTEGER NOT NULL,
+        available INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS ram (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        total_memory INTEGER NOT NULL,
+        used_memory INTEGER NOT NULL,
+        total_swap INTEGER NOT NULL,
+        used_swap INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS sys (
+        id INTEGER PRIMARY KEY,
+        os TEXT NOT NULL,
+        osversion TEXT NOT NULL,
+        hostname TEXT NOT NULL
+    );",
+    // 2: host dimension. Samples from before hosts were tracked can only be attributed to a host
+    // when the database has seen exactly one
+    "CREATE TABLE host (
+        id INTEGER PRIMARY KEY,
+        hostname TEXT NOT NULL UNIQUE
+    );
+    INSERT OR IGNORE INTO host (hostname) SELECT hostname FROM sys ORDER BY id;
+    ALTER TABLE sys ADD COLUMN host_id INTEGER REFERENCES host(id);
+    UPDATE sys SET host_id = (SELECT id FROM host WHERE host.hostname = sys.hostname);
+    ALTER TABLE component ADD COLUMN host_id INTEGER REFERENCES host(id);
+    ALTER TABLE disk ADD COLUMN host_id INTEGER REFERENCES host(id);
+    ALTER TABLE ram ADD COLUMN host_id INTEGER REFERENCES host(id);
+    UPDATE component SET host_id = (SELECT id FROM host) WHERE (SELECT COUNT(*) FROM host) = 1;
+    UPDATE disk SET host_id = (SELECT id FROM host) WHERE (SELECT COUNT(*) FROM host) = 1;
+    UPDATE ram SET host_id = (SELECT id FROM host) WHERE (SELECT COUNT(*) FROM host) = 1;",
+    // 3: configuration history. A sys row is one configuration of a host, rows written before this
+    // migration have no details and were never timestamped
+    "ALTER TABLE sys ADD COLUMN kernel_version TEXT;
+    ALTER TABLE sys ADD COLUMN arch TEXT;
+    ALTER TABLE sys ADD COLUMN cpu_brand TEXT;
+    ALTER TABLE sys ADD COLUMN cpu_cores INTEGER;
+    ALTER TABLE sys ADD COLUMN boot_time DATETIME;
+    ALTER TABLE sys ADD COLUMN first_seen DATETIME;
+    ALTER TABLE sys ADD COLUMN last_seen DATETIME;",
+    // 4: cpu usage, one row per core and one for the whole cpu per sample
+    "CREATE TABLE cpu (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        cpu TEXT NOT NULL,
+        usage REAL NOT NULL,
+        frequency INTEGER NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 5: load and uptime per sample, and events such as reboots that are derived from the samples
+    "CREATE TABLE load (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        one REAL NOT NULL,
+        five REAL NOT NULL,
+        fifteen REAL NOT NULL,
+        uptime INTEGER NOT NULL,
+        boot_time DATETIME NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );
+    CREATE TABLE event (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        kind TEXT NOT NULL,
+        detail TEXT NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 6: network interfaces. The counters are as reported by the kernel, the rates are per second since
+    // the previous sample and NULL when they cannot be known (first sample, counter reset)
+    "CREATE TABLE network (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        interface TEXT NOT NULL,
+        received INTEGER NOT NULL,
+        transmitted INTEGER NOT NULL,
+        packets_received INTEGER NOT NULL,
+        packets_transmitted INTEGER NOT NULL,
+        errors_received INTEGER NOT NULL,
+        errors_transmitted INTEGER NOT NULL,
+        received_rate REAL,
+        transmitted_rate REAL,
+        packets_received_rate REAL,
+        packets_transmitted_rate REAL,
+        errors_received_rate REAL,
+        errors_transmitted_rate REAL,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 7: the top processes of every sample, rank lists the rankings (cpu, memory, ...) a process was kept for
+    "CREATE TABLE process (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        pid INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        cmd TEXT NOT NULL,
+        user TEXT,
+        memory INTEGER NOT NULL,
+        virtual_memory INTEGER NOT NULL,
+        cpu REAL NOT NULL,
+        start_time DATETIME NOT NULL,
+        rank TEXT NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );
+    CREATE INDEX process_name ON process (name, datetime);",
+    // 8: processes starting and exiting, with the peaks observed while they ran
+    "CREATE TABLE process_event (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        event TEXT NOT NULL,
+        pid INTEGER NOT NULL,
+        ppid INTEGER,
+        name TEXT NOT NULL,
+        cmd TEXT NOT NULL,
+        start_time DATETIME NOT NULL,
+        peak_memory INTEGER NOT NULL,
+        peak_cpu REAL NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 9: disks are identified by mount point, device names are often shared (overlay, tmpfs). disk_io
+    // holds what each block device read and wrote since the previous sample
+    "ALTER TABLE disk ADD COLUMN mount_point TEXT;
+    ALTER TABLE disk ADD COLUMN file_system TEXT;
+    ALTER TABLE disk ADD COLUMN removable INTEGER;
+    ALTER TABLE disk ADD COLUMN kind TEXT;
+    CREATE TABLE disk_io (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        device TEXT NOT NULL,
+        seconds REAL NOT NULL,
+        reads INTEGER NOT NULL,
+        writes INTEGER NOT NULL,
+        read_bytes INTEGER NOT NULL,
+        write_bytes INTEGER NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 10: temperatures were stored as INTEGER, truncating them. sqlite cannot change a column's type
+    // so the table is rebuilt. near_critical is decided when the sample is taken, with the margin
+    // configured at the time
+    "CREATE TABLE component_new (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        label TEXT NOT NULL,
+        temp REAL NOT NULL,
+        max REAL,
+        critical REAL,
+        near_critical INTEGER NOT NULL DEFAULT 0,
+        host_id INTEGER REFERENCES host(id)
+    );
+    INSERT INTO component_new (id, datetime, label, temp, host_id) SELECT id, datetime, label, temp, host_id FROM component;
+    DROP TABLE component;
+    ALTER TABLE component_new RENAME TO component;
+    CREATE INDEX component_near_critical ON component (datetime) WHERE near_critical = 1;",
+    // 11: pressure stall information, one row per resource (cpu, memory, io) and kind (some, full).
+    // total is the kernel's cumulative stall time, stalled the part of it since the previous sample
+    "CREATE TABLE pressure (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        resource TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        avg10 REAL NOT NULL,
+        avg60 REAL NOT NULL,
+        avg300 REAL NOT NULL,
+        total INTEGER NOT NULL,
+        stalled INTEGER,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 12: cgroup v2 resource usage, one row per cgroup. The counters are cumulative as the kernel
+    // reports them, a column is NULL when its controller is not enabled for the cgroup
+    "CREATE TABLE cgroup (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        path TEXT NOT NULL,
+        memory_current INTEGER,
+        memory_max INTEGER,
+        cpu_usage INTEGER,
+        cpu_user INTEGER,
+        cpu_system INTEGER,
+        io_read_bytes INTEGER,
+        io_write_bytes INTEGER,
+        io_reads INTEGER,
+        io_writes INTEGER,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 13: inode usage and the read-only flag of each mount. inodes is NULL for filesystems without a
+    // fixed number of inodes (btrfs) and for disks recorded before this migration
+    "ALTER TABLE disk ADD COLUMN inodes INTEGER;
+    ALTER TABLE disk ADD COLUMN inodes_free INTEGER;
+    ALTER TABLE disk ADD COLUMN read_only INTEGER;",
+    // 14: login sessions. session holds the number of sessions open at each sample, session_event
+    // the sessions that started or ended between samples
+    "CREATE TABLE session (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        active INTEGER NOT NULL,
+        users INTEGER NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );
+    CREATE TABLE session_event (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        event TEXT NOT NULL,
+        user TEXT NOT NULL,
+        uid INTEGER,
+        tty TEXT NOT NULL,
+        remote_host TEXT,
+        pid INTEGER NOT NULL,
+        login_time DATETIME NOT NULL,
+        host_id INTEGER REFERENCES host(id)
+    );",
+    // 15: rollups of ram, disk and component samples per minute, hour and day. datetime is the start of
+    // the bucket, one row per host, series (mount point, sensor) and metric. They are filled in from the
+    // samples by the recorder, including the samples already in the database, and rollup_state holds the
+    // last raw row rolled up per table
+    "CREATE TABLE rollup_1m (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        source TEXT NOT NULL,
+        series TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        min REAL NOT NULL,
+        max REAL NOT NULL,
+        sum REAL NOT NULL,
+        last REAL NOT NULL,
+        last_datetime DATETIME NOT NULL,
+        count INTEGER NOT NULL,
+        host_id INTEGER NOT NULL REFERENCES host(id),
+        UNIQUE (source, datetime, host_id, series, metric)
+    );
+    CREATE TABLE rollup_1h (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        source TEXT NOT NULL,
+        series TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        min REAL NOT NULL,
+        max REAL NOT NULL,
+        sum REAL NOT NULL,
+        last REAL NOT NULL,
+        last_datetime DATETIME NOT NULL,
+        count INTEGER NOT NULL,
+        host_id INTEGER NOT NULL REFERENCES host(id),
+        UNIQUE (source, datetime, host_id, series, metric)
+    );
+    CREATE TABLE rollup_1d (
+        id INTEGER PRIMARY KEY,
+        datetime DATETIME NOT NULL,
+        source TEXT NOT NULL,
+        series TEXT NOT NULL,
+        metric TEXT NOT NULL,
+        min REAL NOT NULL,
+        max REAL NOT NULL,
+        sum REAL NOT NULL,
+        last REAL NOT NULL,
+        last_datetime DATETIME NOT NULL,
+        count INTEGER NOT NULL,
+        host_id INTEGER NOT NULL REFERENCES host(id),
+        UNIQUE (source, datetime, host_id, series, metric)
+    );
+    CREATE TABLE rollup_state (
+        source TEXT PRIMARY KEY,
+        last_id INTEGER NOT NULL
+    );",
+    // 16: datetimes become UTC milliseconds since the epoch, stored as INTEGER. The text datetimes were
+    // local times of the recording host, they are read as local times of the host running the migration
+    // (the same one unless the database was moved). The rollups were bucketed by local time, they are
+    // dropped and rebuilt from the samples that are still there
+    "UPDATE component SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE disk SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE disk_io SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE ram SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE cpu SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE load SET
+        datetime = CASE WHEN typeof(datetime) = 'text' THEN CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 ELSE datetime END,
+        boot_time = CASE WHEN typeof(boot_time) = 'text' THEN CAST(strftime('%s', boot_time, 'utc') AS INTEGER) * 1000 ELSE boot_time END;
+    UPDATE pressure SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE cgroup SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE network SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE process SET
+        datetime = CASE WHEN typeof(datetime) = 'text' THEN CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 ELSE datetime END,
+        start_time = CASE WHEN typeof(start_time) = 'text' THEN CAST(strftime('%s', start_time, 'utc') AS INTEGER) * 1000 ELSE start_time END;
+    UPDATE process_event SET
+        datetime = CASE WHEN typeof(datetime) = 'text' THEN CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 ELSE datetime END,
+        start_time = CASE WHEN typeof(start_time) = 'text' THEN CAST(strftime('%s', start_time, 'utc') AS INTEGER) * 1000 ELSE start_time END;
+    UPDATE session SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE session_event SET
+        datetime = CASE WHEN typeof(datetime) = 'text' THEN CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 ELSE datetime END,
+        login_time = CASE WHEN typeof(login_time) = 'text' THEN CAST(strftime('%s', login_time, 'utc') AS INTEGER) * 1000 ELSE login_time END;
+    UPDATE event SET datetime = CAST(strftime('%s', datetime, 'utc') AS INTEGER) * 1000 WHERE typeof(datetime) = 'text';
+    UPDATE sys SET
+        boot_time = CASE WHEN typeof(boot_time) = 'text' THEN CAST(strftime('%s', boot_time, 'utc') AS INTEGER) * 1000 ELSE boot_time END,
+        first_seen = CASE WHEN typeof(first_seen) = 'text' THEN CAST(strftime('%s', first_seen, 'utc') AS INTEGER) * 1000 ELSE first_seen END,
+        last_seen = CASE WHEN typeof(last_seen) = 'text' THEN CAST(strftime('%s', last_seen, 'utc') AS INTEGER) * 1000 ELSE last_seen END;
+    DELETE FROM rollup_1m;
+    DELETE FROM rollup_1h;
+    DELETE FROM rollup_1d;
+    DELETE FROM rollup_state;",
+    // 17: range queries search (host_id, datetime) for each host instead of scanning the table, and a
+    // sensor, mount or interface can be followed over time by (label/name, datetime)
+    "CREATE INDEX component_host_datetime ON component (host_id, datetime);
+    CREATE INDEX disk_host_datetime ON disk (host_id, datetime);
+    CREATE INDEX disk_io_host_datetime ON disk_io (host_id, datetime);
+    CREATE INDEX ram_host_datetime ON ram (host_id, datetime);
+    CREATE INDEX cpu_host_datetime ON cpu (host_id, datetime);
+    CREATE INDEX load_host_datetime ON load (host_id, datetime);
+    CREATE INDEX pressure_host_datetime ON pressure (host_id, datetime);
+    CREATE INDEX cgroup_host_datetime ON cgroup (host_id, datetime);
+    CREATE INDEX network_host_datetime ON network (host_id, datetime);
+    CREATE INDEX process_host_datetime ON process (host_id, datetime);
+    CREATE INDEX process_event_host_datetime ON process_event (host_id, datetime);
+    CREATE INDEX session_host_datetime ON session (host_id, datetime);
+    CREATE INDEX session_event_host_datetime ON session_event (host_id, datetime);
+    CREATE INDEX event_host_datetime ON event (host_id, datetime);
+    CREATE INDEX rollup_1m_host_datetime ON rollup_1m (host_id, datetime);
+    CREATE INDEX rollup_1h_host_datetime ON rollup_1h (host_id, datetime);
+    CREATE INDEX rollup_1d_host_datetime ON rollup_1d (host_id, datetime);
+    CREATE INDEX component_label ON component (label, datetime);
+    CREATE INDEX disk_mount_point ON disk (mount_point, datetime);
+    CREATE INDEX network_interface ON network (interface, datetime);",
+];
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// Brings the database up to the latest schema version, each migration in its own transaction
+pub fn migrate(conn: &mut Connection) -> Result<(), String> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply(conn: &mut Connection, migrations: &[&str]) -> Result<(), String> {
+    let current = schema_version(conn).map_err(|e| format!("Could not read schema version: {}", e))?;
+    if current as usize > migrations.len() {
+        return Err(format!(
+            "Database schema version {} is newer than this program supports ({}), please upgrade sysinfo_database",
+            current, migrations.len()));
+    }
+
+    for (i, sql) in migrations.iter().enumerate().skip(current as usize) {
+        let version = i as u32 + 1;
+        let failed = |e: rusqlite::Error| format!("Migrating the database to schema version {} failed: {}", version, e);
+        let tx = conn.transaction().map_err(failed)?;
+        tx.execute_batch(sql).map_err(failed)?;
+        tx.pragma_update(None, "user_version", version).map_err(failed)?;
+        tx.commit().map_err(failed)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // schema written by create_schema before migrations existed
+    const BASELINE_FIXTURE: &str = "
+        CREATE TABLE component (id INTEGER PRIMARY KEY, datetime DATETIME NOT NULL, label TEXT NOT NULL, temp INTEGER NOT NULL);
+        CREATE TABLE disk (id INTEGER PRIMARY KEY, datetime DATETIME NOT NULL, name TEXT NOT NULL, total INTEGER NOT NULL, available INTEGER NOT NULL);
+        CREATE TABLE ram (id INTEGER PRIMARY KEY, datetime DATETIME NOT NULL, total_memory INTEGER NOT NULL, used_memory INTEGER NOT NULL, total_swap INTEGER NOT NULL, used_swap INTEGER NOT NULL);
+        CREATE TABLE sys (id INTEGER PRIMARY KEY, os TEXT NOT NULL, osversion TEXT NOT NULL, hostname TEXT NOT NULL);
+        INSERT INTO sys (os, osversion, hostname) VALUES ('Debian GNU/Linux', '12', 'vm');
+        INSERT INTO component (datetime, label, temp) VALUES ('2024-01-01 00:00:00', 'cpu', 40);
+        INSERT INTO disk (datetime, name, total, available) VALUES ('2024-01-01 00:00:00', '/dev/sda', 1000, 500);
+        INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap) VALUES ('2024-01-01 00:00:00', 8000, 4000, 2000, 0);
+    ";
+
+    fn latest_version() -> u32 {
+        MIGRATIONS.len() as u32
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn empty_database_is_migrated_to_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        for table in ["component", "disk", "ram", "sys", "cpu", "load", "event", "network", "process", "process_event", "disk_io", "pressure", "cgroup", "session", "session_event", "rollup_1m", "rollup_1h", "rollup_1d", "rollup_state"] {
+            assert_eq!(count(&conn, table), 0);
+        }
+    }
+
+    #[test]
+    fn baseline_database_keeps_its_data() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        for table in ["component", "disk", "ram", "sys"] {
+            assert_eq!(count(&conn, table), 1);
+        }
+    }
+
+    #[test]
+    fn baseline_samples_belong_to_the_only_host() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        migrate(&mut conn).unwrap();
+        for table in ["component", "disk", "ram", "sys"] {
+            let hostname: String = conn.query_row(
+                &format!("SELECT h.hostname FROM {} t JOIN host h ON h.id = t.host_id", table), [], |row| row.get(0)).unwrap();
+            assert_eq!(hostname, "vm");
+        }
+    }
+
+    #[test]
+    fn baseline_samples_from_several_hosts_stay_unattributed() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        conn.execute_batch("INSERT INTO sys (os, osversion, hostname) VALUES ('Debian GNU/Linux', '12', 'other')").unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(count(&conn, "host"), 2);
+        let unattributed: i64 = conn.query_row("SELECT COUNT(*) FROM ram WHERE host_id IS NULL", [], |row| row.get(0)).unwrap();
+        assert_eq!(unattributed, 1);
+    }
+
+    #[test]
+    fn baseline_configuration_has_no_details() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        migrate(&mut conn).unwrap();
+        let (kernel, first_seen): (Option<String>, Option<String>) = conn.query_row(
+            "SELECT kernel_version, first_seen FROM sys", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!((kernel, first_seen), (None, None));
+    }
+
+    #[test]
+    fn baseline_temperatures_become_real_without_thresholds() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        migrate(&mut conn).unwrap();
+        let (kind, critical, near_critical): (String, Option<f64>, bool) = conn.query_row(
+            "SELECT typeof(temp), critical, near_critical FROM component", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        assert_eq!((kind.as_str(), critical, near_critical), ("real", None, false));
+    }
+
+    #[test]
+    fn text_datetimes_become_utc_milliseconds() {
+        use chrono::{Local, TimeZone};
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        migrate(&mut conn).unwrap();
+        // recorded as the local time of the host
+        let expected = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap().timestamp_millis();
+        for table in ["component", "disk", "ram"] {
+            let (kind, datetime): (String, i64) = conn.query_row(
+                &format!("SELECT typeof(datetime), datetime FROM {}", table), [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+            assert_eq!((kind.as_str(), datetime), ("integer", expected));
+        }
+    }
+
+    #[test]
+    fn migrating_twice_is_a_no_op() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        assert_eq!(count(&conn, "ram"), 1);
+    }
+
+    #[test]
+    fn failed_migration_is_rolled_back() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let broken = [MIGRATIONS[0], "CREATE TABLE half_done (id INTEGER); INSERT INTO missing VALUES (1);"];
+        assert!(apply(&mut conn, &broken).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        let exists: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name = 'half_done'", [], |row| row.get(0)).unwrap();
+        assert_eq!(exists, 0);
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        assert!(migrate(&mut conn).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), latest_version() + 1);
+    }
+}
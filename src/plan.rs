@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use rusqlite::{params_from_iter, types::Value, Connection, Result};
+
+// How sqlite answers a query, to catch range queries that read a whole table instead of searching an index
+
+// Read whole by design: the host table has a row per host, constant rows are the parameters
+const SCANNED: &[&str] = &["host", "CONSTANT"];
+
+// The steps of EXPLAIN QUERY PLAN for sql with params bound, indented by their depth in the plan
+pub fn explain(conn: &Connection, sql: &str, params: &[Value]) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+    let mut rows = stmt.query(params_from_iter(params))?;
+    let mut depths: HashMap<i64, usize> = HashMap::new();
+    let mut steps = Vec::new();
+    while let Some(row) = rows.next()? {
+        // id, parent, unused, detail
+        let (id, parent, detail): (i64, i64, String) = (row.get(0)?, row.get(1)?, row.get(3)?);
+        let depth = depths.get(&parent).map_or(0, |depth| depth + 1);
+        depths.insert(id, depth);
+        steps.push(format!("{}{}", "  ".repeat(depth), detail));
+    }
+    Ok(steps)
+}
+
+// The steps of a plan that read a table, or one of its indexes, from start to end
+pub fn full_scans(plan: &[String]) -> Vec<&str> {
+    plan.iter()
+        .map(|step| step.trim_start())
+        .filter(|step| step.strip_prefix("SCAN ")
+            .is_some_and(|scanned| !SCANNED.iter().any(|table| scanned.split(' ').next() == Some(table))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE host (id INTEGER PRIMARY KEY, hostname TEXT NOT NULL UNIQUE);
+            CREATE TABLE ram (id INTEGER PRIMARY KEY, datetime INTEGER NOT NULL, used INTEGER, host_id INTEGER);").unwrap();
+        conn
+    }
+
+    #[test]
+    fn scans_are_found_and_searches_are_not() {
+        let conn = conn();
+        let sql = "SELECT r.used FROM ram r WHERE r.host_id IN (SELECT id FROM host WHERE ?1 IS NULL OR hostname = ?1) AND r.datetime > ?2";
+        let params = [Value::Null, Value::Integer(0)];
+        let plan = explain(&conn, sql, &params).unwrap();
+        assert_eq!(full_scans(&plan), ["SCAN r"]);
+
+        conn.execute_batch("CREATE INDEX ram_host_datetime ON ram (host_id, datetime)").unwrap();
+        let plan = explain(&conn, sql, &params).unwrap();
+        assert!(plan.iter().any(|step| step.contains("SEARCH r USING INDEX ram_host_datetime")), "{:?}", plan);
+        assert!(plan.iter().any(|step| step.starts_with("  ") && step.contains("SCAN host")), "{:?}", plan);
+        assert!(full_scans(&plan).is_empty());
+    }
+}
@@ -0,0 +1,262 @@
+use std::{cell::Cell, sync::{mpsc::{self, Sender, TryRecvError}, Arc, Mutex}, thread::{self, JoinHandle}, time::Duration};
+use rusqlite::{Connection, Result};
+use crate::config::Retention;
+use crate::db::Database;
+use crate::rollup;
+use crate::scheduler::{Control, Scheduler, Wake};
+use crate::timestamp::Timestamp;
+
+// Tables of the samples and events recorded per host, each with a datetime and a host_id. A collector
+// that adds a table lists it here, for retention and for the span of a host's samples.
+pub const SAMPLE_TABLES: &[&str] = &[
+    "component", "disk", "disk_io", "ram", "cpu", "load", "pressure", "cgroup", "network", "process",
+    "process_event", "session", "session_event", "event",
+];
+
+// Tables that retention applies to, the samples and events and then the rollups. sys and host describe
+// the hosts themselves and are always kept. The rollups only expire when given a retention of their own.
+pub fn tables() -> impl Iterator<Item = &'static str> {
+    SAMPLE_TABLES.iter().chain(rollup::TABLES).copied()
+}
+
+// pages handed back to the filesystem per incremental_vacuum call
+const VACUUM_PAGES: i64 = 1000;
+
+// Rows deleted from each table in one maintenance run, and the pages the vacuum freed
+#[derive(Default)]
+pub struct PruneStats {
+    pub deleted: Vec<(&'static str, usize)>,
+    pub freed_pages: i64,
+}
+
+impl PruneStats {
+
+    pub fn summary(&self) -> String {
+        let total: usize = self.deleted.iter().map(|(_, deleted)| deleted).sum();
+        let tables: Vec<String> = self.deleted.iter()
+            .filter(|(_, deleted)| *deleted > 0)
+            .map(|(table, deleted)| format!("{} {}", table, deleted))
+            .collect();
+        match tables.is_empty() {
+            true => format!("Pruned 0 rows, freed {} pages", self.freed_pages),
+            false => format!("Pruned {} rows ({}), freed {} pages", total, tables.join(", "), self.freed_pages),
+        }
+    }
+}
+
+// The maintenance thread of a recording process. Dropping it without stop leaves the thread running.
+pub struct Maintenance {
+    tx: Sender<Control>,
+    handle: JoinHandle<()>,
+}
+
+impl Maintenance {
+
+    // Prunes right away and then every retention.interval seconds, until stopped. Nothing is started
+    // when no retention is configured.
+    pub fn start(conn: Arc<Database>, retention: Retention, log: fn(&str)) -> Option<Maintenance> {
+        if !retention.enabled() {
+            return None;
+        }
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            // the switch rewrites the whole database, too long to hold up recording for
+            if let Ok(false) = incremental_vacuum_enabled(conn.writer()) {
+                log("Freed space stays in the database file until `prune` is run once to switch it to incremental vacuum");
+            }
+            let mut scheduler = Scheduler::new(Duration::from_secs(retention.interval));
+            // a stop read while pruning is gone from the channel, remembered here to end the loop
+            let stopped = Cell::new(false);
+            let stop = || {
+                if !stopped.get() && !matches!(rx.try_recv(), Err(TryRecvError::Empty)) {
+                    stopped.set(true);
+                }
+                stopped.get()
+            };
+            while let Wake::Tick { .. } = scheduler.wait(&rx) {
+                match prune(conn.writer(), &retention, Timestamp::now(), stop) {
+                    Ok(stats) => log(&stats.summary()),
+                    Err(e) => log(&format!("Pruning failed: {}", e)),
+                }
+                if stopped.get() {
+                    break;
+                }
+            }
+        });
+        Some(Maintenance { tx, handle })
+    }
+
+    // waits for the batch being deleted, the rest of the run is abandoned
+    pub fn stop(self) {
+        let _ = self.tx.send(Control::Shutdown);
+        let _ = self.handle.join();
+    }
+}
+
+fn incremental_vacuum_enabled(conn: &Mutex<Connection>) -> Result<bool> {
+    let mode: i64 = conn.lock().unwrap().pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+    Ok(mode == 2)
+}
+
+// incremental_vacuum does nothing unless auto_vacuum is INCREMENTAL, and switching an existing database
+// to it takes a full VACUUM that holds the writer until the whole file is rewritten. Only the prune
+// command does it, once, never a recording process.
+pub fn use_incremental_vacuum(conn: &Mutex<Connection>, log: fn(&str)) -> Result<()> {
+    if !incremental_vacuum_enabled(conn)? {
+        let conn = conn.lock().unwrap();
+        log("Switching the database to incremental vacuum, this rewrites it once");
+        conn.pragma_update(None, "auto_vacuum", 2)?;
+        conn.execute_batch("VACUUM")?;
+    }
+    Ok(())
+}
+
+// Deletes the rows each table's retention has expired, batch rows per statement, then gives the freed
+// pages back. The lock is taken per statement so a recorder sharing the writer is only held up
+// briefly. stop is checked between statements.
+pub fn prune(conn: &Mutex<Connection>, retention: &Retention, now: Timestamp, stop: impl Fn() -> bool) -> Result<PruneStats> {
+    let mut stats = PruneStats::default();
+    for table in tables() {
+        let Some(keep) = retention.keep(table) else {
+            continue;
+        };
+        let cutoff = now.minus_secs(keep);
+        let sql = format!("DELETE FROM {table} WHERE id IN (SELECT id FROM {table} WHERE datetime < ?1 LIMIT ?2)");
+        let mut deleted = 0;
+        loop {
+            if stop() {
+                stats.deleted.push((table, deleted));
+                return Ok(stats);
+            }
+            let batch = conn.lock().unwrap().execute(&sql, (cutoff, retention.batch))?;
+            deleted += batch;
+            if batch < retention.batch {
+                break;
+            }
+        }
+        stats.deleted.push((table, deleted));
+    }
+
+    loop {
+        let conn = conn.lock().unwrap();
+        let free: i64 = conn.pragma_query_value(None, "freelist_count", |row| row.get(0))?;
+        // without incremental auto_vacuum the free pages stay, nothing to wait for
+        let mode: i64 = conn.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+        if free == 0 || mode != 2 || stop() {
+            break;
+        }
+        conn.execute_batch(&format!("PRAGMA incremental_vacuum({})", VACUUM_PAGES))?;
+        stats.freed_pages += free.min(VACUUM_PAGES);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::migrations;
+    use crate::timestamp::Zone;
+
+    fn retention(default: Option<u64>, tables: &[(&str, Option<u64>)], batch: usize) -> Retention {
+        Retention {
+            default,
+            tables: tables.iter().map(|(table, keep)| (table.to_string(), *keep)).collect::<HashMap<_, _>>(),
+            batch,
+            interval: 3600,
+        }
+    }
+
+    fn conn_with_samples(conn: Connection) -> Mutex<Connection> {
+        let mut conn = conn;
+        migrations::migrate(&mut conn).unwrap();
+        for day in 1..=9 {
+            let dt = Timestamp::parse(&format!("2024-01-0{} 12:00:00", day), &Zone::Utc).unwrap();
+            conn.execute("INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap) VALUES (?1, 1, 1, 1, 1)", [dt]).unwrap();
+            conn.execute("INSERT INTO event (datetime, kind, detail) VALUES (?1, 'reboot', '')", [dt]).unwrap();
+            conn.execute("INSERT INTO process (datetime, pid, name, cmd, memory, virtual_memory, cpu, start_time, rank)
+                VALUES (?1, 1, 'init', '', 1, 1, 0, ?1, 'cpu')", [dt]).unwrap();
+        }
+        Mutex::new(conn)
+    }
+
+    fn count(conn: &Mutex<Connection>, table: &str) -> i64 {
+        conn.lock().unwrap().query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    }
+
+    fn now() -> Timestamp {
+        Timestamp::parse("2024-01-09 18:00:00", &Zone::Utc).unwrap()
+    }
+
+    #[test]
+    fn expired_rows_are_deleted_in_batches() {
+        let conn = conn_with_samples(Connection::open_in_memory().unwrap());
+        // two days of everything, one day of processes and events forever
+        let retention = retention(Some(2 * 86400), &[("process", Some(86400)), ("event", None)], 2);
+        let stats = prune(&conn, &retention, now(), || false).unwrap();
+        assert_eq!((count(&conn, "ram"), count(&conn, "process"), count(&conn, "event")), (2, 1, 9));
+        assert!(stats.deleted.contains(&("ram", 7)) && stats.deleted.contains(&("process", 8)));
+        assert!(!stats.deleted.iter().any(|(table, _)| *table == "event"));
+        assert_eq!(stats.summary(), "Pruned 15 rows (ram 7, process 8), freed 0 pages");
+    }
+
+    #[test]
+    fn stopping_abandons_the_run_between_batches() {
+        let conn = conn_with_samples(Connection::open_in_memory().unwrap());
+        let calls = std::cell::Cell::new(0);
+        let stop = || {
+            calls.set(calls.get() + 1);
+            calls.get() > 2
+        };
+        prune(&conn, &retention(None, &[("ram", Some(86400)), ("process", Some(86400))], 3), now(), stop).unwrap();
+        // two batches of three ram rows made it
+        assert_eq!(count(&conn, "ram"), 3);
+        assert_eq!(count(&conn, "process"), 9);
+    }
+
+    #[test]
+    fn stop_during_a_prune_ends_the_maintenance_thread() {
+        let db = Arc::new(Database::open_in_memory());
+        {
+            let conn = db.writer().lock().unwrap();
+            for day in 1..=5 {
+                let dt = Timestamp::parse(&format!("2024-01-0{} 12:00:00", day), &Zone::Utc).unwrap();
+                conn.execute("INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap) VALUES (?1, 1, 1, 1, 1)", [dt]).unwrap();
+            }
+        }
+        // the first batch waits for the writer, so the stop arrives in the middle of the run
+        let writer = db.writer().lock().unwrap();
+        let maintenance = Maintenance::start(db.clone(), retention(None, &[("ram", Some(86400))], 1), |_| {}).unwrap();
+        let (done_tx, done_rx) = mpsc::channel();
+        thread::spawn(move || {
+            maintenance.stop();
+            let _ = done_tx.send(());
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(writer);
+        assert!(done_rx.recv_timeout(Duration::from_secs(5)).is_ok(), "the maintenance thread kept running");
+        // the batch that was waiting went through, the rest of the run did not
+        assert!(count(db.writer(), "ram") >= 4);
+    }
+
+    #[test]
+    fn freed_pages_are_vacuumed() {
+        let path = std::env::temp_dir().join(format!("sysinfo_db_test_{}_retention.db", std::process::id()));
+        let conn = conn_with_samples(Connection::open(&path).unwrap());
+        {
+            let conn = conn.lock().unwrap();
+            let padding = "x".repeat(4000);
+            for _ in 0..200 {
+                conn.execute("INSERT INTO event (datetime, kind, detail) VALUES (0, 'test', ?1)", [&padding]).unwrap();
+            }
+        }
+        use_incremental_vacuum(&conn, |_| {}).unwrap();
+        let pages = |conn: &Mutex<Connection>| -> i64 { conn.lock().unwrap().pragma_query_value(None, "page_count", |row| row.get(0)).unwrap() };
+        let before = pages(&conn);
+        let stats = prune(&conn, &retention(Some(86400), &[], 1000), now(), || false).unwrap();
+        assert!(stats.freed_pages > 100, "{}", stats.freed_pages);
+        assert!(pages(&conn) < before - 100);
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use rusqlite::{Connection, OptionalExtension, Result};
+use crate::timestamp::{Timestamp, SECOND};
+
+// Aggregates of the ram, disk and component samples per minute, hour and day, for trends over ranges
+// too long to read the raw samples for. They are kept up to date from the raw tables as samples are
+// written, a watermark per table in rollup_state remembers how far that got. Buckets are aligned to UTC,
+// so a day is a UTC day whatever timezone it is shown in.
+
+pub const TABLES: &[&str] = &["rollup_1m", "rollup_1h", "rollup_1d"];
+
+// raw rows rolled up per table and sample. A database recorded before rollups existed catches up over
+// the samples that follow
+pub const BATCH: usize = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Minute,
+    Hour,
+    Day,
+}
+
+const RESOLUTIONS: [Resolution; 3] = [Resolution::Minute, Resolution::Hour, Resolution::Day];
+
+impl Resolution {
+
+    pub fn table(self) -> &'static str {
+        TABLES[self as usize]
+    }
+
+    // The coarsest resolution that still shows a range in enough detail, None when only the raw samples
+    // do. Each step keeps a range to about ten thousand points per series: a day of 10 second samples,
+    // a week of minutes, a year of hours.
+    pub fn for_range(start: Timestamp, end: Timestamp) -> Option<Resolution> {
+        const DAY: i64 = 86400 * SECOND;
+        match end.millis() - start.millis() {
+            span if span <= DAY => None,
+            span if span <= 7 * DAY => Some(Resolution::Minute),
+            span if span <= 366 * DAY => Some(Resolution::Hour),
+            _ => Some(Resolution::Day),
+        }
+    }
+
+    // start of the bucket dt falls in
+    fn bucket(self, dt: Timestamp) -> Timestamp {
+        dt.truncate(match self {
+            Resolution::Minute => 60 * SECOND,
+            Resolution::Hour => 3600 * SECOND,
+            Resolution::Day => 86400 * SECOND,
+        })
+    }
+}
+
+// Picks the query for a range: raw when no rollup is coarse enough, otherwise the one of rollups (one per
+// resolution, in the order of TABLES) that fits
+pub fn by_resolution(start: Timestamp, end: Timestamp, raw: &'static str, rollups: [&'static str; 3]) -> &'static str {
+    match Resolution::for_range(start, end) {
+        Some(resolution) => rollups[resolution as usize],
+        None => raw,
+    }
+}
+
+// A raw table that is rolled up, the expression naming a series in it and the columns that are its metrics
+struct Source {
+    table: &'static str,
+    series: &'static str,
+    metrics: &'static [&'static str],
+}
+
+const SOURCES: &[Source] = &[
+    Source { table: "ram", series: "''", metrics: &["total_memory", "used_memory", "total_swap", "used_swap"] },
+    // disks recorded before mount points were tracked only have the device name
+    Source { table: "disk", series: "COALESCE(mount_point, name)", metrics: &["total", "available", "inodes", "inodes_free"] },
+    Source { table: "component", series: "label", metrics: &["temp", "critical", "near_critical"] },
+];
+
+// Rolls up the raw rows written since the last call, at most limit per table. Returns the rows rolled up.
+// Each table is rolled up in a savepoint: when it fails part way the buckets it already added to are put
+// back along with its watermark, so the same rows aren't added twice on the next call.
+pub fn update(conn: &Connection, limit: usize) -> Result<usize> {
+    let mut rolled = 0;
+    for source in SOURCES {
+        conn.execute_batch("SAVEPOINT rollup")?;
+        match update_source(conn, source, limit) {
+            Ok(rows) => {
+                conn.execute_batch("RELEASE rollup")?;
+                rolled += rows;
+            }
+            Err(e) => {
+                conn.execute_batch("ROLLBACK TO rollup; RELEASE rollup")?;
+                return Err(e);
+            }
+        }
+    }
+    Ok(rolled)
+}
+
+fn update_source(conn: &Connection, source: &Source, limit: usize) -> Result<usize> {
+    let stored: i64 = conn.prepare_cached("SELECT last_id FROM rollup_state WHERE source = ?1")?
+        .query_row([source.table], |row| row.get(0))
+        .optional()?
+        .unwrap_or(0);
+    // once retention has emptied a table sqlite hands out its ids from 1 again
+    let max_id: i64 = conn.prepare_cached(&format!("SELECT IFNULL(MAX(id), 0) FROM {}", source.table))?
+        .query_row([], |row| row.get(0))?;
+    let mut last_id = if max_id < stored { 0 } else { stored };
+
+    let mut stmt = conn.prepare_cached(&format!("SELECT id, datetime, host_id, {}, {} FROM {} WHERE id > ?1 ORDER BY id LIMIT ?2",
+        source.series, source.metrics.join(", "), source.table))?;
+    let mut rows = stmt.query((last_id, limit))?;
+    let mut rolled = 0;
+    while let Some(row) = rows.next()? {
+        last_id = row.get(0)?;
+        let datetime: Timestamp = row.get(1)?;
+        // samples from before hosts were tracked that could not be attributed to one are left out
+        let Some(host_id) = row.get::<_, Option<i64>>(2)? else {
+            continue;
+        };
+        let series: String = row.get(3)?;
+        for (i, metric) in source.metrics.iter().enumerate() {
+            let Some(value) = row.get::<_, Option<f64>>(4 + i)? else {
+                continue;
+            };
+            for resolution in RESOLUTIONS {
+                add(conn, resolution, resolution.bucket(datetime), source.table, &series, metric, value, datetime, host_id)?;
+            }
+        }
+        rolled += 1;
+    }
+
+    if last_id != stored {
+        conn.prepare_cached("INSERT INTO rollup_state (source, last_id) VALUES (?1, ?2)
+            ON CONFLICT (source) DO UPDATE SET last_id = excluded.last_id")?
+            .execute((source.table, last_id))?;
+    }
+    Ok(rolled)
+}
+
+// Adds one value to its bucket. The average is kept as sum and count so it can be extended, last is the
+// value with the latest datetime in case rows arrive out of order.
+#[allow(clippy::too_many_arguments)]
+fn add(conn: &Connection, resolution: Resolution, bucket: Timestamp, source: &str, series: &str, metric: &str, value: f64,
+    datetime: Timestamp, host_id: i64) -> Result<()> {
+    let mut stmt = conn.prepare_cached(&format!("INSERT INTO {} (datetime, source, series, metric, min, max, sum, last, last_datetime, count, host_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?5, ?5, ?6, 1, ?7)
+        ON CONFLICT (source, datetime, host_id, series, metric) DO UPDATE SET
+            min = MIN(min, excluded.min),
+            max = MAX(max, excluded.max),
+            sum = sum + excluded.sum,
+            last = CASE WHEN excluded.last_datetime >= last_datetime THEN excluded.last ELSE last END,
+            last_datetime = MAX(last_datetime, excluded.last_datetime),
+            count = count + 1", resolution.table()))?;
+    stmt.execute((bucket, source, series, metric, value, datetime, host_id))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use crate::timestamp::Zone;
+
+    fn ts(dt: &str) -> Timestamp {
+        Timestamp::parse(dt, &Zone::Utc).unwrap()
+    }
+
+    fn conn() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        conn.execute("INSERT INTO host (hostname) VALUES ('alpha')", []).unwrap();
+        conn
+    }
+
+    fn ram(conn: &Connection, dt: &str, used: u64) {
+        conn.execute("INSERT INTO ram (datetime, total_memory, used_memory, total_swap, used_swap, host_id) VALUES (?1, 8000, ?2, 0, 0, 1)",
+            (ts(dt), used)).unwrap();
+    }
+
+    // min, max, avg, last and count of used_memory in a bucket
+    fn used(conn: &Connection, table: &str, bucket: &str) -> (f64, f64, f64, f64, i64) {
+        conn.query_row(&format!("SELECT min, max, sum / count, last, count FROM {} WHERE source = 'ram' AND metric = 'used_memory' AND datetime = ?1", table),
+            [ts(bucket)], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap()
+    }
+
+    #[test]
+    fn samples_are_aggregated_per_bucket() {
+        let conn = conn();
+        ram(&conn, "2024-01-01 10:00:10", 1000);
+        ram(&conn, "2024-01-01 10:00:50", 3000);
+        ram(&conn, "2024-01-01 10:01:10", 8000);
+        assert_eq!(update(&conn, BATCH).unwrap(), 3);
+        assert_eq!(used(&conn, "rollup_1m", "2024-01-01 10:00:00"), (1000.0, 3000.0, 2000.0, 3000.0, 2));
+        assert_eq!(used(&conn, "rollup_1m", "2024-01-01 10:01:00"), (8000.0, 8000.0, 8000.0, 8000.0, 1));
+        assert_eq!(used(&conn, "rollup_1h", "2024-01-01 10:00:00"), (1000.0, 8000.0, 4000.0, 8000.0, 3));
+        assert_eq!(used(&conn, "rollup_1d", "2024-01-01 00:00:00"), (1000.0, 8000.0, 4000.0, 8000.0, 3));
+    }
+
+    #[test]
+    fn rows_are_rolled_up_once_in_batches() {
+        let conn = conn();
+        for second in 0..5 {
+            ram(&conn, &format!("2024-01-01 10:00:0{}", second), 1000);
+        }
+        assert_eq!(update(&conn, 2).unwrap(), 2);
+        assert_eq!(update(&conn, 2).unwrap(), 2);
+        ram(&conn, "2024-01-01 10:00:30", 7000);
+        assert_eq!(update(&conn, 2).unwrap(), 2);
+        assert_eq!(update(&conn, 2).unwrap(), 0);
+        assert_eq!(used(&conn, "rollup_1m", "2024-01-01 10:00:00"), (1000.0, 7000.0, 2000.0, 7000.0, 6));
+    }
+
+    #[test]
+    fn emptied_tables_are_rolled_up_from_the_start() {
+        let conn = conn();
+        ram(&conn, "2024-01-01 10:00:00", 1000);
+        ram(&conn, "2024-01-01 10:00:10", 1000);
+        update(&conn, BATCH).unwrap();
+        conn.execute("DELETE FROM ram", []).unwrap();
+        ram(&conn, "2024-01-02 10:00:00", 5000);
+        assert_eq!(update(&conn, BATCH).unwrap(), 1);
+        assert_eq!(used(&conn, "rollup_1d", "2024-01-02 00:00:00"), (5000.0, 5000.0, 5000.0, 5000.0, 1));
+    }
+
+    #[test]
+    fn optional_metrics_and_unattributed_rows_are_skipped() {
+        let conn = conn();
+        conn.execute("INSERT INTO disk (datetime, name, total, available, host_id) VALUES (?1, 'sda', 1000, 500, 1)", [ts("2024-01-01 10:00:00")]).unwrap();
+        conn.execute("INSERT INTO disk (datetime, name, total, available) VALUES (?1, 'sdb', 1000, 500)", [ts("2024-01-01 10:00:00")]).unwrap();
+        update(&conn, BATCH).unwrap();
+        let metrics: Vec<String> = conn.prepare("SELECT series || ' ' || metric FROM rollup_1m ORDER BY metric").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(metrics, ["sda available", "sda total"]);
+    }
+
+    #[test]
+    fn failed_updates_leave_no_partial_buckets() {
+        let conn = conn();
+        ram(&conn, "2024-01-01 10:00:10", 1000);
+        // the minute and hour buckets are added to before the day one fails
+        conn.execute_batch("ALTER TABLE rollup_1d RENAME TO rollup_1d_gone").unwrap();
+        assert!(update(&conn, BATCH).is_err());
+        let rows: i64 = conn.query_row("SELECT (SELECT COUNT(*) FROM rollup_1m) + (SELECT COUNT(*) FROM rollup_1h) + (SELECT COUNT(*) FROM rollup_state)",
+            [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 0);
+
+        conn.execute_batch("ALTER TABLE rollup_1d_gone RENAME TO rollup_1d").unwrap();
+        assert_eq!(update(&conn, BATCH).unwrap(), 1);
+        assert_eq!(used(&conn, "rollup_1m", "2024-01-01 10:00:00"), (1000.0, 1000.0, 1000.0, 1000.0, 1));
+    }
+
+    #[test]
+    fn longer_ranges_use_coarser_resolutions() {
+        let range = |end: &str| Resolution::for_range(ts("2024-01-01 00:00:00"), ts(end));
+        assert_eq!(range("2024-01-02 00:00:00"), None);
+        assert_eq!(range("2024-01-02 00:00:01"), Some(Resolution::Minute));
+        assert_eq!(range("2024-01-08 00:00:00"), Some(Resolution::Minute));
+        assert_eq!(range("2024-01-09 00:00:00"), Some(Resolution::Hour));
+        assert_eq!(range("2025-06-01 00:00:00"), Some(Resolution::Day));
+        assert_eq!(Resolution::for_range(ts("2024-01-08 00:00:00"), ts("2024-01-01 00:00:00")), None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{sync::mpsc::{Receiver, RecvTimeoutError}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+// Messages that control a recording thread
+pub enum Control {
+    // stop taking samples until told to record again
+    Pause,
+    Record,
+    // record and print every sample
+    Live,
+    Reload,
+    Shutdown,
+}
+
+pub enum Wake<T> {
+    // a sample is due, missed is how many earlier ticks were skipped because the previous sample overran
+    Tick { missed: u64 },
+    Message(T),
+    Disconnected,
+}
+
+// Fixed rate ticks aligned to wall clock multiples of the interval, so the time spent taking a
+// sample never pushes later samples back
+pub struct Scheduler {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl Scheduler {
+
+    pub fn new(interval: Duration) -> Self {
+        Scheduler { interval, next: None }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        if interval != self.interval {
+            self.interval = interval;
+            self.next = Some(self.next_boundary());
+        }
+    }
+
+    // the next wait fires immediately, used when recording resumes after a pause
+    pub fn reset(&mut self) {
+        self.next = None;
+    }
+
+    // Blocks until the next tick is due or a message arrives, whichever comes first
+    pub fn wait<T>(&mut self, rx: &Receiver<T>) -> Wake<T> {
+        let next = match self.next {
+            Some(next) => next,
+            None => {
+                self.next = Some(self.next_boundary());
+                return Wake::Tick { missed: 0 };
+            }
+        };
+
+        let now = Instant::now();
+        if now < next {
+            match rx.recv_timeout(next - now) {
+                Ok(message) => return Wake::Message(message),
+                Err(RecvTimeoutError::Disconnected) => return Wake::Disconnected,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+
+        Wake::Tick { missed: self.advance(next, Instant::now()) }
+    }
+
+    // Schedules the tick after the one due at next, woken up at now. Ticks now is already past are
+    // skipped and counted, how late the wake up was never moves the schedule.
+    fn advance(&mut self, next: Instant, now: Instant) -> u64 {
+        let late = now.saturating_duration_since(next);
+        let missed = (late.as_nanos() / self.interval.as_nanos()) as u64;
+        self.next = Some(next + self.interval * (missed + 1) as u32);
+        missed
+    }
+
+    fn next_boundary(&self) -> Instant {
+        boundary_after(self.interval, SystemTime::now(), Instant::now())
+    }
+}
+
+// the instant of the first wall clock multiple of interval after wall, which is the time at now
+fn boundary_after(interval: Duration, wall: SystemTime, now: Instant) -> Instant {
+    let interval = interval.as_nanos();
+    let since_epoch = wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let until_boundary = interval - since_epoch % interval;
+    now + Duration::from_nanos(until_boundary as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    fn tick<T>(scheduler: &mut Scheduler, rx: &Receiver<T>) -> u64 {
+        match scheduler.wait(rx) {
+            Wake::Tick { missed } => missed,
+            _ => panic!("expected a tick"),
+        }
+    }
+
+    #[test]
+    fn first_tick_is_immediate() {
+        let (_tx, rx) = mpsc::channel::<Control>();
+        let mut scheduler = Scheduler::new(Duration::from_secs(3600));
+        let start = Instant::now();
+        assert_eq!(tick(&mut scheduler, &rx), 0);
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn work_time_does_not_cause_drift() {
+        let mut scheduler = Scheduler::new(INTERVAL);
+        let first = Instant::now();
+        let mut next = first;
+        for _ in 0..4 {
+            // woken up 30ms after each tick was due, as if the previous sample ran long
+            assert_eq!(scheduler.advance(next, next + Duration::from_millis(30)), 0);
+            next = scheduler.next.unwrap();
+        }
+        // a drifting schedule would be 4 * 30ms behind
+        assert_eq!(next - first, INTERVAL * 4);
+    }
+
+    #[test]
+    fn ticks_are_aligned_to_the_wall_clock() {
+        let now = Instant::now();
+        let wall = UNIX_EPOCH + Duration::from_millis(1_700_000_001_234);
+        assert_eq!(boundary_after(Duration::from_secs(1), wall, now) - now, Duration::from_millis(766));
+        // on a boundary the next one is a whole interval away
+        let wall = UNIX_EPOCH + Duration::from_secs(1_700_000_010);
+        assert_eq!(boundary_after(Duration::from_secs(10), wall, now) - now, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn overrunning_sample_reports_missed_ticks() {
+        let mut scheduler = Scheduler::new(INTERVAL);
+        let due = Instant::now();
+        assert_eq!(scheduler.advance(due, due + INTERVAL * 3 + Duration::from_millis(10)), 3);
+        // the schedule picks up again at the next boundary
+        assert_eq!(scheduler.next, Some(due + INTERVAL * 4));
+    }
+
+    #[test]
+    fn messages_wake_the_scheduler() {
+        let (tx, rx) = mpsc::channel();
+        let mut scheduler = Scheduler::new(Duration::from_secs(3600));
+        tick(&mut scheduler, &rx);
+        tx.send(Control::Shutdown).unwrap();
+        assert!(matches!(scheduler.wait(&rx), Wake::Message(Control::Shutdown)));
+        drop(tx);
+        assert!(matches!(scheduler.wait(&rx), Wake::Disconnected));
+    }
+}
@@ -0,0 +1,193 @@
+use std::{fmt, str::FromStr, sync::OnceLock, time::{SystemTime, UNIX_EPOCH}};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+
+// How datetimes are written and read by people, always in the zone they are shown in
+pub const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub const SECOND: i64 = 1000;
+
+// A point in time as milliseconds since the Unix epoch, UTC. This is what the database stores, so
+// samples from hosts in different timezones and from either side of a DST change compare correctly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+
+    pub fn now() -> Timestamp {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Timestamp(since_epoch.as_millis() as i64)
+    }
+
+    // the kernel and utmp count in seconds
+    pub fn from_secs(secs: u64) -> Timestamp {
+        Timestamp(secs as i64 * SECOND)
+    }
+
+    pub fn millis(self) -> i64 {
+        self.0
+    }
+
+    // the start of the period of the given length this falls in, periods counted from the epoch
+    pub fn truncate(self, period: i64) -> Timestamp {
+        Timestamp(self.0 - self.0.rem_euclid(period))
+    }
+
+    // saturates, a span reaching back further than a timestamp can is cut off at the earliest one
+    pub fn minus_secs(self, secs: u64) -> Timestamp {
+        let millis = i64::try_from(secs).ok().and_then(|secs| secs.checked_mul(SECOND));
+        Timestamp(millis.and_then(|millis| self.0.checked_sub(millis)).unwrap_or(i64::MIN))
+    }
+
+    // Reads "YYYY-MM-DD HH:MM:SS" as a time in zone. A time the clocks skipped over is an error, one
+    // they passed twice is read as the first.
+    pub fn parse(text: &str, zone: &Zone) -> Result<Timestamp, String> {
+        let naive = NaiveDateTime::parse_from_str(text, FORMAT)
+            .map_err(|_| format!("invalid datetime '{}', expected YYYY-MM-DD HH:MM:SS", text))?;
+        let millis = match zone {
+            Zone::Local => Local.from_local_datetime(&naive).earliest().map(|dt| dt.timestamp_millis()),
+            Zone::Utc => Some(naive.and_utc().timestamp_millis()),
+            Zone::Fixed(offset) => offset.from_local_datetime(&naive).earliest().map(|dt| dt.timestamp_millis()),
+            Zone::Named(tz) => tz.from_local_datetime(&naive).earliest().map(|dt| dt.timestamp_millis()),
+        };
+        millis.map(Timestamp).ok_or_else(|| format!("datetime '{}' does not exist in {}", text, zone))
+    }
+
+    pub fn format(self, zone: &Zone) -> String {
+        let Some(utc) = DateTime::<Utc>::from_timestamp_millis(self.0) else {
+            return self.0.to_string();
+        };
+        match zone {
+            Zone::Local => utc.with_timezone(&Local).format(FORMAT).to_string(),
+            Zone::Utc => utc.format(FORMAT).to_string(),
+            Zone::Fixed(offset) => utc.with_timezone(offset).format(FORMAT).to_string(),
+            Zone::Named(tz) => utc.with_timezone(tz).format(FORMAT).to_string(),
+        }
+    }
+}
+
+// in the zone chosen for display
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(zone()))
+    }
+}
+
+impl ToSql for Timestamp {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0))
+    }
+}
+
+impl FromSql for Timestamp {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(Timestamp)
+    }
+}
+
+// The timezone datetimes are shown and entered in: the machine's, UTC, a fixed offset or an IANA name
+#[derive(Clone, Debug, PartialEq)]
+pub enum Zone {
+    Local,
+    Utc,
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Zone {
+
+    pub fn parse(name: &str) -> Result<Zone, String> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if name.eq_ignore_ascii_case("utc") || name == "Z" {
+            return Ok(Zone::Utc);
+        }
+        if name.starts_with(['+', '-']) {
+            return FixedOffset::from_str(name).map(Zone::Fixed)
+                .map_err(|_| format!("Invalid timezone offset '{}', expected e.g. +02:00", name));
+        }
+        Tz::from_str(name).map(Zone::Named)
+            .map_err(|_| format!("Unknown timezone '{}', expected local, UTC, an offset like +02:00 or a name like Europe/Berlin", name))
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Zone::Local => write!(f, "the local timezone"),
+            Zone::Utc => write!(f, "UTC"),
+            Zone::Fixed(offset) => write!(f, "{}", offset),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+static ZONE: OnceLock<Zone> = OnceLock::new();
+
+// Chooses the zone for the rest of the process, only the first call has an effect
+pub fn set_zone(zone: Zone) {
+    let _ = ZONE.set(zone);
+}
+
+// The zone to show and read datetimes in. Tests use UTC so they don't depend on the machine's timezone.
+pub fn zone() -> &'static Zone {
+    ZONE.get_or_init(|| if cfg!(test) { Zone::Utc } else { Zone::Local })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetimes_are_read_and_shown_in_a_zone() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        let winter = Timestamp::parse("2024-01-15 12:00:00", &berlin).unwrap();
+        assert_eq!(winter.format(&Zone::Utc), "2024-01-15 11:00:00");
+        let summer = Timestamp::parse("2024-07-15 12:00:00", &berlin).unwrap();
+        assert_eq!(summer.format(&Zone::Utc), "2024-07-15 10:00:00");
+        assert_eq!(summer.format(&Zone::parse("+05:30").unwrap()), "2024-07-15 15:30:00");
+        assert_eq!(Timestamp::parse("1970-01-01 00:00:01", &Zone::Utc).unwrap(), Timestamp::from_secs(1));
+    }
+
+    #[test]
+    fn dst_changes_are_unambiguous() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        // 02:30 happened twice on 2024-10-27, once in summer time and an hour later in winter time
+        let first = Timestamp::parse("2024-10-27 02:30:00", &berlin).unwrap();
+        assert_eq!(first.format(&Zone::Utc), "2024-10-27 00:30:00");
+        let second = Timestamp::parse("2024-10-27 01:30:00", &Zone::Utc).unwrap();
+        assert!(second > first);
+        assert_eq!(second.format(&berlin), "2024-10-27 02:30:00");
+        // and never on 2024-03-31
+        assert!(Timestamp::parse("2024-03-31 02:30:00", &berlin).is_err());
+    }
+
+    #[test]
+    fn zones_are_parsed() {
+        assert_eq!(Zone::parse("local").unwrap(), Zone::Local);
+        assert_eq!(Zone::parse("UTC").unwrap(), Zone::Utc);
+        assert_eq!(Zone::parse("-03:00").unwrap(), Zone::Fixed(FixedOffset::west_opt(3 * 3600).unwrap()));
+        assert!(Zone::parse("Mars/Olympus_Mons").is_err());
+        assert!(Zone::parse("+25:00").is_err());
+        assert!(Timestamp::parse("2024-01-01", &Zone::Utc).is_err());
+    }
+
+    #[test]
+    fn long_spans_saturate() {
+        let ts = Timestamp::parse("2024-01-15 12:00:00", &Zone::Utc).unwrap();
+        assert_eq!(ts.minus_secs(86400).format(&Zone::Utc), "2024-01-14 12:00:00");
+        assert_eq!(ts.minus_secs(u64::MAX), Timestamp(i64::MIN));
+        assert_eq!(Timestamp(i64::MIN + 1).minus_secs(1), Timestamp(i64::MIN));
+    }
+
+    #[test]
+    fn timestamps_truncate_to_periods() {
+        let ts = Timestamp::parse("2024-01-15 12:34:56", &Zone::Utc).unwrap();
+        assert_eq!(ts.truncate(60 * SECOND).format(&Zone::Utc), "2024-01-15 12:34:00");
+        assert_eq!(ts.truncate(86400 * SECOND).format(&Zone::Utc), "2024-01-15 00:00:00");
+        assert_eq!(Timestamp(-1).truncate(SECOND), Timestamp(-1000));
+    }
+}